@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// In-memory cache of file bytes shared by the search and content stages
+/// Entries are keyed by path and modification time, so a file changed in between is read again.
+/// Once `max_bytes` are cached, further files are read but not stored.
+#[derive(Debug, Default)]
+pub struct FileCache {
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<PathBuf, (SystemTime, Arc<Vec<u8>>)>,
+    bytes: usize,
+}
+
+impl FileCache {
+    /// Creates a cache holding up to `max_bytes` of file contents, or disabled if 0
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Reads a file, returning the cached bytes if it hasn't been modified since
+    pub fn read(&self, path: &Path) -> io::Result<Arc<Vec<u8>>> {
+        if self.max_bytes == 0 {
+            return fs::read(path).map(Arc::new);
+        }
+
+        let mtime = fs::metadata(path)?.modified()?;
+        self.get_or_read(path, mtime, || fs::read(path))
+    }
+
+    /// Returns the cached bytes for `path` at `version`, or stores the result of `read`
+    /// Sources without modification times can pass a fixed version to read each file once
+    pub fn get_or_read<E>(
+        &self,
+        path: &Path,
+        version: SystemTime,
+        read: impl FnOnce() -> Result<Vec<u8>, E>,
+    ) -> Result<Arc<Vec<u8>>, E> {
+        if let Some((cached_version, bytes)) = self.lock().entries.get(path) {
+            if *cached_version == version {
+                return Ok(Arc::clone(bytes));
+            }
+        }
+
+        let bytes = Arc::new(read()?);
+        let mut state = self.lock();
+        let replaced = state.entries.get(path).map_or(0, |(_, old)| old.len());
+        if state.bytes - replaced + bytes.len() <= self.max_bytes {
+            state.bytes = state.bytes - replaced + bytes.len();
+            state.entries.insert(path.to_path_buf(), (version, Arc::clone(&bytes)));
+        }
+        Ok(bytes)
+    }
+
+    /// Total size of the cached file contents in bytes
+    pub fn cached_bytes(&self) -> usize {
+        self.lock().bytes
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        // A panic while holding the lock can't leave the map inconsistent, so keep using it
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_cached() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("a.org");
+        fs::write(&path, "hello").unwrap();
+
+        let cache = FileCache::new(1024);
+        assert_eq!(cache.read(&path).unwrap().as_slice(), b"hello");
+        assert_eq!(cache.cached_bytes(), 5);
+
+        // A second read returns the same bytes without growing the cache
+        let first = cache.read(&path).unwrap();
+        let second = cache.read(&path).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(cache.cached_bytes(), 5);
+    }
+
+    #[test]
+    fn test_read_bounded() {
+        let temp_dir = tempdir().unwrap();
+        let small = temp_dir.path().join("small.org");
+        let large = temp_dir.path().join("large.org");
+        fs::write(&small, "abc").unwrap();
+        fs::write(&large, "x".repeat(100)).unwrap();
+
+        let cache = FileCache::new(10);
+        cache.read(&small).unwrap();
+        assert_eq!(cache.read(&large).unwrap().len(), 100);
+        assert_eq!(cache.cached_bytes(), 3);
+
+        // A disabled cache still reads files
+        let disabled = FileCache::new(0);
+        assert_eq!(disabled.read(&small).unwrap().as_slice(), b"abc");
+        assert_eq!(disabled.cached_bytes(), 0);
+    }
+}
@@ -1,35 +1,511 @@
 use anyhow::{Context, Result};
+use clap::ValueEnum;
 use dirs::home_dir;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 
 // Configuration structures
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub knowledge: KnowledgeConfig,
     pub ollama: OllamaConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub content: ContentConfig,
+    #[serde(default)]
+    pub prompts: PromptsConfig,
+    #[serde(default)]
+    pub extract: ExtractConfig,
+    #[serde(default)]
+    pub cli: CliConfig,
+    /// Extra keywords searched alongside a term, keyed by the term
+    #[serde(default)]
+    pub synonyms: HashMap<String, Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Placeholder shown instead of secrets
+const REDACTED: &str = "<redacted>";
+
+impl Config {
+    /// Returns a copy with the auth token and header values replaced by a placeholder
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        if config.ollama.auth_token.is_some() {
+            config.ollama.auth_token = Some(REDACTED.to_string());
+        }
+        for value in config.ollama.headers.values_mut() {
+            *value = REDACTED.to_string();
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OllamaConfig {
     pub endpoint: String,
     pub model: String,
     pub max_context_length: usize,
+    /// Bearer token sent in the Authorization header
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    /// Additional headers sent with every request
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Strip thinking sections from generated responses
+    #[serde(default)]
+    pub strip_thinking: bool,
+    /// Opening and closing delimiters of a thinking section
+    #[serde(default = "default_thinking_delimiters")]
+    pub thinking_delimiters: [String; 2],
+    /// Which part of the context is kept when it exceeds `max_context_length`
+    #[serde(default)]
+    pub truncation_strategy: TruncationStrategy,
+    /// Maximum number of generate requests in flight at once, e.g. while reranking
+    #[serde(default = "default_max_concurrency")]
+    pub max_concurrency: NonZeroUsize,
+    /// Sequences that end the generated answer where they appear
+    #[serde(default)]
+    pub stop: Vec<String>,
+}
+
+fn default_max_concurrency() -> NonZeroUsize {
+    NonZeroUsize::MIN
+}
+
+/// Which part of an overlong context is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncationStrategy {
+    /// Keep the beginning
+    #[default]
+    Head,
+    /// Keep the end
+    Tail,
+    /// Keep both ends and drop the middle
+    MiddleOut,
+}
+
+fn default_thinking_delimiters() -> [String; 2] {
+    ["<think>".to_string(), "</think>".to_string()]
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KnowledgeConfig {
+    #[serde(default)]
     pub root_path: String,
+    /// Additional knowledge base roots searched alongside `root_path`
+    #[serde(default)]
+    pub roots: Vec<RootConfig>,
     pub max_files: usize,
+    /// Gitignore-style patterns for files to skip, combined with `.brainignore`
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Extensions of the files to search, unless `include` is set
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<String>,
+    /// Globs, relative to the root, of the files to search
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Globs, relative to the root, of files to leave out of the search
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Suffix of the metadata file next to each note, e.g. `.meta` for `note.txt.meta`
+    #[serde(default)]
+    pub sidecar_suffix: Option<String>,
+    /// Where the files are read from: the local filesystem or an HTTP server
+    #[serde(default)]
+    pub source: SourceKind,
+    /// URL of the server when `source` is `http`
+    #[serde(default)]
+    pub base_url: Option<String>,
+}
+
+/// Kind of knowledge source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// Files under `root_path` and `roots`
+    #[default]
+    Filesystem,
+    /// Files listed and served by the server at `base_url`
+    Http,
+}
+
+fn default_extensions() -> Vec<String> {
+    vec!["org".to_string()]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootConfig {
+    pub path: String,
+    /// Multiplier for the relevance of files under this root
+    #[serde(default = "default_root_weight")]
+    pub weight: f64,
+}
+
+fn default_root_weight() -> f64 {
+    1.0
+}
+
+impl KnowledgeConfig {
+    /// Returns every configured knowledge base root with its weight
+    pub fn roots(&self) -> Vec<(&str, f64)> {
+        let mut roots = Vec::new();
+        if !self.root_path.is_empty() {
+            roots.push((self.root_path.as_str(), default_root_weight()));
+        }
+        roots.extend(self.roots.iter().map(|r| (r.path.as_str(), r.weight)));
+        roots
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchConfig {
+    /// Stop scanning once `max_files` results have reached `min_score`
+    /// Saves little with `idf_weighting`, whose first pass reads every file anyway.
+    #[serde(default)]
+    pub early_exit: bool,
+    /// Score a result must reach to count towards an early exit
+    #[serde(default)]
+    pub min_score: f64,
+    /// Multiplier applied to matches of terms recalled by the model
+    #[serde(default = "default_recalled_weight")]
+    pub recalled_weight: f64,
+    /// Multiplier applied to matches in a file's body
+    #[serde(default = "default_body_weight")]
+    pub body_weight: f64,
+    /// Multiplier applied to matches in a file's headings, on top of their body match
+    #[serde(default)]
+    pub heading_weight: f64,
+    /// Multiplier applied to matches in a file's name, off by default
+    #[serde(default)]
+    pub filename_weight: f64,
+    /// Order of the search results
+    #[serde(default)]
+    pub sort: SortOrder,
+    /// Score repeated matches of a keyword with diminishing returns, as ln(1 + matches)
+    #[serde(default)]
+    pub saturation: bool,
+    /// Weight each keyword by its inverse document frequency across the searched files
+    #[serde(default)]
+    pub idf_weighting: bool,
+    /// Reward files where matches of different keywords occur close together
+    #[serde(default)]
+    pub proximity: bool,
+    /// Size, in bytes, of the window keyword matches must fall into to count as close
+    #[serde(default = "default_proximity_window")]
+    pub proximity_window: usize,
+    /// Score added for each additional keyword that matches within the window
+    #[serde(default = "default_proximity_boost")]
+    pub proximity_boost: f64,
+    /// Only return files matching every `prop:KEY=value` clause, rather than boosting them
+    #[serde(default)]
+    pub require_properties: bool,
+    /// Match unscoped terms against headings and file names only, not file bodies
+    #[serde(default)]
+    pub titles_only: bool,
+    /// Ignore diacritics when matching, so that "cafe" matches "café"
+    #[serde(default)]
+    pub fold_diacritics: bool,
+    /// Relevance the best match needs for a response to be generated confidently
+    #[serde(default)]
+    pub generate_min_relevance: f64,
+    /// What to do when the best match is below `generate_min_relevance`
+    #[serde(default)]
+    pub low_relevance: LowRelevance,
+    /// Keywords whose presence in a file's body excludes or penalizes it
+    #[serde(default)]
+    pub exclude_terms: Vec<String>,
+    /// Multiplier for the score of files containing an excluded term, 0 to drop them
+    #[serde(default)]
+    pub exclude_weight: f64,
+    /// Number of top results the model reranks with --rerank
+    #[serde(default = "default_rerank_candidates")]
+    pub rerank_candidates: usize,
+    /// Number of characters from the start of each file the model sees when reranking
+    #[serde(default = "default_rerank_excerpt_chars")]
+    pub rerank_excerpt_chars: usize,
+    /// Time after which the scan stops and ranks the files matched so far
+    #[serde(default)]
+    pub max_duration_ms: Option<u64>,
+    /// Maximum number of results from any single directory, at least 1
+    #[serde(default)]
+    pub max_per_dir: Option<NonZeroUsize>,
+    /// Multiplier applied to matches of synonyms from the `[synonyms]` section
+    #[serde(default = "default_synonym_weight")]
+    pub synonym_weight: f64,
+}
+
+fn default_rerank_candidates() -> usize {
+    20
+}
+
+fn default_rerank_excerpt_chars() -> usize {
+    1000
+}
+
+/// Handling of queries whose best match isn't relevant enough
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LowRelevance {
+    /// Report that there is no confident match instead of generating a response
+    #[default]
+    Skip,
+    /// Generate a response anyway, with a disclaimer
+    Disclaimer,
+}
+
+/// Order of search results, applied before truncating to `max_files`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Most relevant first
+    #[default]
+    Relevance,
+    /// Alphabetically by path
+    Path,
+    /// Most recently modified first
+    Mtime,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            early_exit: false,
+            min_score: 0.0,
+            recalled_weight: default_recalled_weight(),
+            body_weight: default_body_weight(),
+            heading_weight: 0.0,
+            filename_weight: 0.0,
+            sort: SortOrder::default(),
+            saturation: false,
+            idf_weighting: false,
+            proximity: false,
+            proximity_window: default_proximity_window(),
+            proximity_boost: default_proximity_boost(),
+            require_properties: false,
+            titles_only: false,
+            fold_diacritics: false,
+            generate_min_relevance: 0.0,
+            low_relevance: LowRelevance::default(),
+            exclude_terms: Vec::new(),
+            exclude_weight: 0.0,
+            rerank_candidates: default_rerank_candidates(),
+            rerank_excerpt_chars: default_rerank_excerpt_chars(),
+            max_duration_ms: None,
+            max_per_dir: None,
+            synonym_weight: default_synonym_weight(),
+        }
+    }
+}
+
+fn default_proximity_window() -> usize {
+    200
+}
+
+fn default_proximity_boost() -> f64 {
+    1.0
+}
+
+fn default_recalled_weight() -> f64 {
+    0.5
+}
+
+fn default_synonym_weight() -> f64 {
+    0.5
 }
 
-/// Loads the configuration from the default path (~/.config/brain/config.toml)
+fn default_body_weight() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentConfig {
+    /// Marker inserted before each highlighted keyword match
+    #[serde(default = "default_highlight_marker")]
+    pub highlight_open: String,
+    /// Marker inserted after each highlighted keyword match
+    #[serde(default = "default_highlight_marker")]
+    pub highlight_close: String,
+    /// Total number of characters of text content to retrieve, shared between files
+    #[serde(default)]
+    pub max_total_chars: Option<usize>,
+    /// Bytes of file contents kept in memory so each file is read once per run, 0 to disable
+    #[serde(default = "default_cache_max_bytes")]
+    pub cache_max_bytes: usize,
+}
+
+impl Default for ContentConfig {
+    fn default() -> Self {
+        Self {
+            highlight_open: default_highlight_marker(),
+            highlight_close: default_highlight_marker(),
+            max_total_chars: None,
+            cache_max_bytes: default_cache_max_bytes(),
+        }
+    }
+}
+
+fn default_highlight_marker() -> String {
+    "**".to_string()
+}
+
+fn default_cache_max_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptsConfig {
+    /// Separators that split the model's term list when it isn't returned as JSON
+    #[serde(default = "default_term_separators")]
+    pub term_separators: Vec<String>,
+    /// Style of generated answers, if any
+    #[serde(default)]
+    pub answer_style: Option<AnswerStyle>,
+    /// Instructions replacing the built-in ones for each answer style
+    #[serde(default)]
+    pub answer_styles: HashMap<AnswerStyle, String>,
+    /// Language answers are written in, instead of the language of the query
+    #[serde(default)]
+    pub answer_language: Option<String>,
+}
+
+impl Default for PromptsConfig {
+    fn default() -> Self {
+        Self {
+            term_separators: default_term_separators(),
+            answer_style: None,
+            answer_styles: HashMap::new(),
+            answer_language: None,
+        }
+    }
+}
+
+impl PromptsConfig {
+    /// Returns the instruction for the configured answer style, if one is set
+    pub fn answer_instruction(&self) -> Option<&str> {
+        let style = self.answer_style?;
+        Some(
+            self.answer_styles
+                .get(&style)
+                .map(String::as_str)
+                .unwrap_or_else(|| style.default_instruction()),
+        )
+    }
+}
+
+/// Length and format of generated answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AnswerStyle {
+    /// A short answer of one or two sentences
+    Concise,
+    /// A thorough explanation
+    Detailed,
+    /// A bulleted list
+    Bullets,
+}
+
+impl AnswerStyle {
+    /// Returns the built-in instruction for the style
+    fn default_instruction(self) -> &'static str {
+        match self {
+            AnswerStyle::Concise => "Answer concisely, in one or two sentences.",
+            AnswerStyle::Detailed => "Answer in detail, explaining the relevant background and reasoning.",
+            AnswerStyle::Bullets => "Answer as a bulleted list of short points.",
+        }
+    }
+}
+
+fn default_term_separators() -> Vec<String> {
+    vec!["\n".to_string(), ",".to_string()]
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtractConfig {
+    /// How search terms are derived from the query
+    #[serde(default)]
+    pub method: ExtractMethod,
+    /// Reduce words to their stem with the local method, so that e.g. "features" also finds "featured"
+    #[serde(default)]
+    pub stem: bool,
+}
+
+/// Method of search term extraction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtractMethod {
+    /// Ask the model for search terms
+    #[default]
+    Llm,
+    /// Derive keywords from the query locally, without contacting Ollama
+    Local,
+}
+
+/// Defaults for command-line flags
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CliConfig {
+    /// Mode used when --mode isn't given
+    #[serde(default)]
+    pub default_mode: Option<Mode>,
+    /// Output format used when --format isn't given
+    #[serde(default)]
+    pub default_format: Option<OutputFormat>,
+}
+
+/// Operation mode for the brain tool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Only extract and display search terms
+    ExtractOnly,
+    /// Extract terms and find matching files
+    SearchOnly,
+    /// Complete workflow including response generation
+    #[default]
+    GenerateResponse,
+}
+
+/// Output format for the brain tool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Standard text output
+    #[default]
+    Text,
+    /// JSON formatted output
+    Json,
+}
+
+/// Name of the project config file, looked up from the current directory upward
+pub const PROJECT_CONFIG_FILE: &str = ".brain.toml";
+
+/// Loads the project config if there is one, or else the configuration from the default path
+/// (~/.config/brain/config.toml)
+/// A project config is announced on stderr, as it's picked up without being asked for.
 pub fn load_config() -> Result<Config> {
-    let config_path = get_default_config_path()?;
+    let project_config = std::env::current_dir().ok().and_then(|dir| find_project_config(&dir));
+    let config_path = match project_config {
+        Some(path) => {
+            eprintln!("Using project config: {}", path.display());
+            path
+        }
+        None => get_default_config_path()?,
+    };
     load_config_from_path(&config_path)
 }
 
+/// Returns the nearest `.brain.toml` in `dir` or one of its ancestors
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
 /// Loads the configuration from a specific path
 pub fn load_config_from_path(config_path: &Path) -> Result<Config> {
     if !config_path.exists() {
@@ -39,21 +515,118 @@ pub fn load_config_from_path(config_path: &Path) -> Result<Config> {
     let config_str = fs::read_to_string(config_path)
         .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
 
-    let config: Config = toml::from_str(&config_str)
+    let table: toml::Table = toml::from_str(&config_str)
+        .with_context(|| format!("Failed to parse config file: {}", config_path.display()))?;
+    if !table.contains_key("extends") {
+        // Parse the text itself, so that errors point at a line of the file
+        let config: Config = toml::from_str(&config_str)
+            .with_context(|| format!("Failed to parse config file: {}", config_path.display()))?;
+        return Ok(config);
+    }
+
+    let table = load_config_table(config_path, &mut Vec::new())?;
+    let config: Config = toml::Value::Table(table)
+        .try_into()
         .with_context(|| format!("Failed to parse config file: {}", config_path.display()))?;
 
     Ok(config)
 }
 
+/// Loads a config file as a table, overlaid on the base config it `extends`, if any
+/// `chain` holds the files being loaded, to detect files extending each other.
+fn load_config_table(config_path: &Path, chain: &mut Vec<PathBuf>) -> Result<toml::Table> {
+    let canonical = config_path
+        .canonicalize()
+        .with_context(|| format!("Config file not found: {}", config_path.display()))?;
+    if chain.contains(&canonical) {
+        return Err(anyhow::anyhow!(
+            "Config files extend each other: {} -> {}",
+            chain.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(" -> "),
+            canonical.display()
+        ));
+    }
+
+    let config_str = fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
+    let mut table: toml::Table = toml::from_str(&config_str)
+        .with_context(|| format!("Failed to parse config file: {}", config_path.display()))?;
+
+    let Some(extends) = table.remove("extends") else {
+        return Ok(table);
+    };
+    let extends = extends
+        .as_str()
+        .with_context(|| format!("`extends` must be a path in config file: {}", config_path.display()))?;
+
+    // Relative paths are resolved against the directory of the extending file, and `~/` against the home directory
+    let base_path = match (extends.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => canonical.parent().unwrap_or(Path::new("")).join(extends),
+    };
+    chain.push(canonical);
+    let mut base = load_config_table(&base_path, chain)?;
+    chain.pop();
+
+    drop_credentials_for_new_endpoint(&mut base, &table);
+    merge_tables(&mut base, table);
+    Ok(base)
+}
+
+/// Removes the base's auth token and headers if the overlay changes `ollama.endpoint`
+/// Credentials meant for one server are never sent to another one named by an extending file.
+fn drop_credentials_for_new_endpoint(base: &mut toml::Table, overlay: &toml::Table) {
+    let endpoint = |table: &toml::Table| table.get("ollama").and_then(|ollama| ollama.get("endpoint")).cloned();
+    let Some(new_endpoint) = endpoint(overlay) else {
+        return;
+    };
+    if endpoint(base).as_ref() == Some(&new_endpoint) {
+        return;
+    }
+    if let Some(toml::Value::Table(ollama)) = base.get_mut("ollama") {
+        ollama.remove("auth_token");
+        ollama.remove("headers");
+    }
+}
+
+/// Overlays `overlay` on `base`, merging nested tables and replacing any other values
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
 /// Returns the default configuration file path
+/// Falls back to `$XDG_CONFIG_HOME/brain/config.toml`, then `./brain.toml`, without a home directory
 pub fn get_default_config_path() -> Result<PathBuf> {
-    let config_path = home_dir()
-        .context("Could not determine home directory")?
-        .join(".config")
-        .join("brain")
-        .join("config.toml");
-    
-    Ok(config_path)
+    let xdg_config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from);
+    resolve_config_path(home_dir(), xdg_config_home, Path::new("brain.toml"))
+}
+
+/// Picks the first available config location, listing every location checked if none is
+fn resolve_config_path(home: Option<PathBuf>, xdg_config_home: Option<PathBuf>, local: &Path) -> Result<PathBuf> {
+    if let Some(home) = home {
+        return Ok(home.join(".config").join("brain").join("config.toml"));
+    }
+    if let Some(dir) = xdg_config_home {
+        return Ok(dir.join("brain").join("config.toml"));
+    }
+    if local.exists() {
+        return Ok(local.to_path_buf());
+    }
+
+    Err(anyhow::anyhow!(
+        "Could not find a config file. Checked: home directory (not found), $XDG_CONFIG_HOME (unset), {} (not found). Use --config to specify one",
+        local.display()
+    ))
 }
 
 /// Creates a test configuration for testing purposes
@@ -62,13 +635,34 @@ pub fn create_test_config_for_tests(root_path: &Path) -> Config {
     Config {
         knowledge: KnowledgeConfig {
             root_path: root_path.to_string_lossy().to_string(),
+            roots: Vec::new(),
             max_files: 5,
+            ignore: Vec::new(),
+            extensions: default_extensions(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            sidecar_suffix: None,
+            source: SourceKind::default(),
+            base_url: None,
         },
         ollama: OllamaConfig {
             endpoint: "http://localhost:11434".to_string(),
             model: "mistral".to_string(),
             max_context_length: 4096,
+            auth_token: None,
+            headers: HashMap::new(),
+            strip_thinking: false,
+            thinking_delimiters: default_thinking_delimiters(),
+            truncation_strategy: TruncationStrategy::default(),
+            max_concurrency: default_max_concurrency(),
+            stop: Vec::new(),
         },
+        search: SearchConfig::default(),
+        content: ContentConfig::default(),
+        prompts: PromptsConfig::default(),
+        extract: ExtractConfig::default(),
+        cli: CliConfig::default(),
+        synonyms: HashMap::new(),
     }
 }
 
@@ -116,6 +710,112 @@ max_context_length = 4096
         drop(temp_dir);
     }
 
+    #[test]
+    fn test_load_config_extends() {
+        let temp_dir = tempdir().unwrap();
+        let shared = temp_dir.path().join("shared");
+        fs::create_dir(&shared).unwrap();
+        fs::write(
+            shared.join("base.toml"),
+            r#"
+[knowledge]
+root_path = "/notes"
+max_files = 5
+
+[ollama]
+endpoint = "http://localhost:11434"
+model = "llama2"
+max_context_length = 4096
+"#,
+        )
+        .unwrap();
+        let config_path = temp_dir.path().join("work.toml");
+        fs::write(
+            &config_path,
+            r#"
+extends = "shared/base.toml"
+
+[knowledge]
+root_path = "/work"
+"#,
+        )
+        .unwrap();
+
+        // Values of the extending file win, and sections are merged key by key
+        let config = load_config_from_path(&config_path).unwrap();
+        assert_eq!(config.knowledge.root_path, "/work");
+        assert_eq!(config.knowledge.max_files, 5);
+        assert_eq!(config.ollama.model, "llama2");
+
+        // Files extending each other are an error rather than endless recursion
+        fs::write(shared.join("base.toml"), "extends = \"../work.toml\"\n").unwrap();
+        let error = load_config_from_path(&config_path).unwrap_err();
+        assert!(error.to_string().contains("extend each other"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_load_config_extends_new_endpoint() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(
+            temp_dir.path().join("base.toml"),
+            r#"
+[knowledge]
+root_path = "/notes"
+max_files = 5
+
+[ollama]
+endpoint = "https://ollama.example.com"
+model = "llama2"
+max_context_length = 4096
+auth_token = "secret"
+
+[ollama.headers]
+X-Api-Key = "secret"
+"#,
+        )
+        .unwrap();
+        let config_path = temp_dir.path().join("project.toml");
+
+        // The credentials are inherited as long as the endpoint stays the same
+        fs::write(&config_path, "extends = \"base.toml\"\n\n[ollama]\nmodel = \"mistral\"\n").unwrap();
+        let config = load_config_from_path(&config_path).unwrap();
+        assert_eq!(config.ollama.auth_token.as_deref(), Some("secret"));
+        assert_eq!(config.ollama.headers.len(), 1);
+
+        // but not sent to an endpoint named by the extending file
+        fs::write(
+            &config_path,
+            "extends = \"base.toml\"\n\n[ollama]\nendpoint = \"https://elsewhere.example.com\"\n",
+        )
+        .unwrap();
+        let config = load_config_from_path(&config_path).unwrap();
+        assert_eq!(config.ollama.endpoint, "https://elsewhere.example.com");
+        assert_eq!(config.ollama.auth_token, None);
+        assert!(config.ollama.headers.is_empty());
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let temp_dir = tempdir().unwrap();
+        let nested = temp_dir.path().join("project").join("notes").join("2024");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_project_config(&nested), None);
+
+        // The nearest config wins over one further up
+        let outer = temp_dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(&outer, "").unwrap();
+        assert_eq!(find_project_config(&nested), Some(outer));
+        let inner = temp_dir.path().join("project").join(PROJECT_CONFIG_FILE);
+        fs::write(&inner, "").unwrap();
+        assert_eq!(find_project_config(&nested), Some(inner));
+
+        drop(temp_dir);
+    }
+
     #[test]
     fn test_load_config() {
         let (temp_dir, _) = create_test_config();
@@ -162,4 +862,70 @@ max_context_length = 4096
         // Clean up
         drop(temp_dir);
     }
+
+    #[test]
+    fn test_redacted() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        config.ollama.auth_token = Some("secret".to_string());
+        config.ollama.headers.insert("X-Api-Key".to_string(), "secret".to_string());
+
+        let shown = toml::to_string(&config.redacted()).unwrap();
+        assert!(!shown.contains("secret"));
+        assert!(shown.contains("auth_token = \"<redacted>\""));
+
+        let parsed: Config = toml::from_str(&shown).unwrap();
+        assert_eq!(parsed.knowledge.root_path, config.knowledge.root_path);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_answer_instruction() {
+        let mut prompts: PromptsConfig = toml::from_str("[answer_styles]\nbullets = \"Use dashes.\"").unwrap();
+        assert_eq!(prompts.answer_instruction(), None);
+
+        prompts.answer_style = Some(AnswerStyle::Bullets);
+        assert_eq!(prompts.answer_instruction(), Some("Use dashes."));
+
+        prompts.answer_style = Some(AnswerStyle::Concise);
+        assert_eq!(prompts.answer_instruction(), Some(AnswerStyle::Concise.default_instruction()));
+    }
+
+    #[test]
+    fn test_max_per_dir_nonzero() {
+        let search: SearchConfig = toml::from_str("max_per_dir = 2").unwrap();
+        assert_eq!(search.max_per_dir, NonZeroUsize::new(2));
+
+        // A cap of 0 would drop every result
+        assert!(toml::from_str::<SearchConfig>("max_per_dir = 0").is_err());
+    }
+
+    #[test]
+    fn test_max_concurrency_nonzero() {
+        let ollama = "endpoint = \"localhost\"\nmodel = \"m\"\nmax_context_length = 4096\n";
+        let config: OllamaConfig = toml::from_str(&format!("{}max_concurrency = 4", ollama)).unwrap();
+        assert_eq!(config.max_concurrency, NonZeroUsize::new(4).unwrap());
+
+        // No request could ever be sent with 0 slots
+        assert!(toml::from_str::<OllamaConfig>(&format!("{}max_concurrency = 0", ollama)).is_err());
+    }
+
+    #[test]
+    fn test_resolve_config_path_without_home() {
+        let temp_dir = tempdir().unwrap();
+        let local = temp_dir.path().join("brain.toml");
+
+        let xdg = resolve_config_path(None, Some(temp_dir.path().to_path_buf()), &local).unwrap();
+        assert_eq!(xdg, temp_dir.path().join("brain").join("config.toml"));
+
+        let error = resolve_config_path(None, None, &local).unwrap_err().to_string();
+        assert!(error.contains("$XDG_CONFIG_HOME"));
+        assert!(error.contains(&local.display().to_string()));
+
+        fs::write(&local, "").unwrap();
+        assert_eq!(resolve_config_path(None, None, &local).unwrap(), local);
+
+        drop(temp_dir);
+    }
 }
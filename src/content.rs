@@ -1,36 +1,473 @@
-use anyhow::Result;
-use std::collections::HashMap;
-use std::fs;
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Serialize, Serializer};
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::ops::Range;
 use std::path::Path;
 
-/// Retrieves the contents of the specified files
-pub fn get_contents(file_paths: &[String]) -> Result<String> {
-    let mut contents = HashMap::new();
+use crate::parser::parser_for;
+use crate::search::keyword_pattern;
+use crate::source::KnowledgeSource;
+use crate::Warnings;
 
-    for path in file_paths {
-        let file_path = Path::new(path);
-        if file_path.exists() {
-            match fs::read_to_string(file_path) {
-                Ok(content) => {
-                    contents.insert(path.clone(), content);
-                }
-                Err(e) => {
-                    eprintln!("Error reading file {}: {}", path, e);
-                    contents.insert(path.clone(), format!("Error reading file: {}", e));
+/// Content of a retrieved file
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum FileContent {
+    /// Text content, serialized as a plain string
+    Text(String),
+    /// Binary content, base64-encoded with a guessed MIME type
+    Binary { mime: String, base64: String },
+}
+
+impl FileContent {
+    /// Builds the content from raw bytes, detecting whether they are text
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        // Treat null bytes or invalid UTF-8 as a sign of binary content
+        if !bytes.contains(&0) {
+            match String::from_utf8(bytes) {
+                Ok(text) => return FileContent::Text(normalize_text(text)),
+                Err(e) => return Self::binary(e.into_bytes()),
+            }
+        }
+        Self::binary(bytes)
+    }
+
+    fn binary(bytes: Vec<u8>) -> Self {
+        let mime = infer::get(&bytes)
+            .map(|kind| kind.mime_type())
+            .unwrap_or("application/octet-stream");
+
+        FileContent::Binary {
+            mime: mime.to_string(),
+            base64: BASE64.encode(&bytes),
+        }
+    }
+}
+
+/// Strips a leading UTF-8 byte order mark and converts Windows line endings to `\n`
+pub fn normalize_text(text: String) -> String {
+    let text = match text.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => text,
+    };
+    if text.contains("\r\n") {
+        text.replace("\r\n", "\n")
+    } else {
+        text
+    }
+}
+
+/// File contents keyed by path, serialized as a JSON object in the order the files were given
+struct Contents {
+    files: Vec<(String, FileContent)>,
+    /// Paths of the files only read up to the character cap, whose text continues in the file
+    partial: HashSet<String>,
+}
+
+impl Serialize for Contents {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.files.iter().map(|(path, content)| (path, content)))
+    }
+}
+
+impl Contents {
+    /// Truncates text contents to a total number of characters, shared fairly between files,
+    /// and notes the number of characters left out at the end of each truncated file
+    fn cap(&mut self, max_total_chars: usize) {
+        let notices = self.truncate(max_total_chars);
+        self.append_notices(notices);
+    }
+
+    /// Truncates text contents like `cap`, returning the notice due to each truncated file by index
+    /// Files shorter than their share are kept whole, and the rest of their share goes to the others
+    fn truncate(&mut self, max_total_chars: usize) -> Vec<(usize, String)> {
+        let partial = &self.partial;
+        let mut texts: Vec<(usize, &mut String, usize, bool)> = self
+            .files
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, (path, content))| match content {
+                FileContent::Text(text) => {
+                    let len = text.chars().count();
+                    Some((index, text, len, partial.contains(path.as_str())))
                 }
+                FileContent::Binary { .. } => None,
+            })
+            .collect();
+        texts.sort_by_key(|(_, _, len, _)| *len);
+
+        let mut notices = Vec::new();
+        let mut budget = max_total_chars;
+        let count = texts.len();
+        for (i, (index, text, len, partial)) in texts.into_iter().enumerate() {
+            let allowed = len.min(budget / (count - i));
+            budget -= allowed;
+            if allowed < len {
+                let end = text.char_indices().nth(allowed).map_or(text.len(), |(i, _)| i);
+                text.truncate(end);
+                // The rest of a partially read file wasn't counted
+                let more = if partial { "at least " } else { "" };
+                notices.push((index, format!("... [truncated, {}{} more chars]", more, len - allowed)));
             }
-        } else {
-            contents.insert(path.clone(), "File not found".to_string());
+        }
+        notices
+    }
+
+    /// Appends the notices returned by `truncate` to their files
+    fn append_notices(&mut self, notices: Vec<(usize, String)>) {
+        for (index, notice) in notices {
+            if let (_, FileContent::Text(text)) = &mut self.files[index] {
+                text.push_str(&notice);
+            }
+        }
+    }
+}
+
+/// Retrieves the contents of the specified files, capped to `max_total_chars` if given
+/// Files that can't be read are reported to `warnings`.
+pub fn get_contents(
+    file_paths: &[String],
+    max_total_chars: Option<usize>,
+    source: &dyn KnowledgeSource,
+    warnings: &Warnings,
+) -> Result<String> {
+    let mut contents = read_contents(file_paths, max_total_chars, source, warnings);
+    if let Some(max_total_chars) = max_total_chars {
+        contents.cap(max_total_chars);
+    }
+    Ok(serde_json::to_string_pretty(&contents)?)
+}
+
+/// Retrieves an outline of each file instead of its full content: its heading lines and,
+/// with `lead`, the first non-empty line under each heading
+/// Files without headings are represented by their first non-empty line.
+pub fn get_outline(
+    file_paths: &[String],
+    lead: bool,
+    max_total_chars: Option<usize>,
+    source: &dyn KnowledgeSource,
+    warnings: &Warnings,
+) -> Result<String> {
+    // Headings anywhere in a file make up its outline, so files are read whole
+    let mut contents = read_contents(file_paths, None, source, warnings);
+    for (path, content) in contents.files.iter_mut() {
+        if let FileContent::Text(text) = content {
+            *text = outline(text, Path::new(path), lead);
+        }
+    }
+    if let Some(max_total_chars) = max_total_chars {
+        contents.cap(max_total_chars);
+    }
+    Ok(serde_json::to_string_pretty(&contents)?)
+}
+
+/// Merges the specified files into one Markdown document, titled `title`, with a table of contents
+/// and a section per file headed by its path. Text is capped to `max_total_chars` like
+/// `get_contents`, and binary files are only named.
+pub fn get_bundle(
+    title: &str,
+    file_paths: &[String],
+    max_total_chars: Option<usize>,
+    source: &dyn KnowledgeSource,
+    warnings: &Warnings,
+) -> Result<String> {
+    let mut contents = read_contents(file_paths, max_total_chars, source, warnings);
+    if let Some(max_total_chars) = max_total_chars {
+        contents.cap(max_total_chars);
+    }
+
+    let mut bundle = format!("# {}\n\n## Contents\n\n", title);
+    for (i, (path, _)) in contents.files.iter().enumerate() {
+        bundle.push_str(&format!("{}. [{}](#file-{})\n", i + 1, path, i + 1));
+    }
+    for (i, (path, content)) in contents.files.iter().enumerate() {
+        bundle.push_str(&format!("\n<a id=\"file-{}\"></a>\n\n## {}\n\n", i + 1, path));
+        match content {
+            FileContent::Text(text) => {
+                // Fence the text with more backticks than it contains in a row, so it can't end the block
+                let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+                let fence = "`".repeat(longest_run.max(2) + 1);
+                let language = Path::new(path).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+                bundle.push_str(&format!("{}{}\n{}\n{}\n", fence, language, text.trim_end_matches('\n'), fence));
+            }
+            FileContent::Binary { mime, .. } => bundle.push_str(&format!("Binary file ({}), not included.\n", mime)),
+        }
+    }
+    Ok(bundle)
+}
+
+/// Builds the outline of a document for `get_outline`
+fn outline(text: &str, path: &Path, lead: bool) -> String {
+    let parser = parser_for(path);
+    let mut lines = Vec::new();
+    let mut wants_lead = false;
+    for line in text.lines() {
+        if parser.heading(line).is_some() {
+            lines.push(line);
+            wants_lead = lead;
+        } else if wants_lead && !line.trim().is_empty() {
+            lines.push(line);
+            wants_lead = false;
+        }
+    }
+
+    if lines.is_empty() {
+        return text.lines().find(|line| !line.trim().is_empty()).unwrap_or_default().to_string();
+    }
+    lines.join("\n")
+}
+
+/// Builds the patterns used to highlight keywords
+fn keyword_patterns(keywords: &[String]) -> Result<Vec<regex::Regex>> {
+    keywords.iter().map(|k| keyword_pattern(k)).collect()
+}
+
+/// Wraps every keyword match in a piece of text in the given markers
+pub fn highlight_keywords(text: &str, keywords: &[String], open: &str, close: &str) -> Result<String> {
+    Ok(highlight(text, &keyword_patterns(keywords)?, open, close))
+}
+
+/// Retrieves the contents of the specified files, wrapping keyword matches in the given markers
+pub fn get_contents_highlighted(
+    file_paths: &[String],
+    keywords: &[String],
+    open: &str,
+    close: &str,
+    max_total_chars: Option<usize>,
+    source: &dyn KnowledgeSource,
+    warnings: &Warnings,
+) -> Result<String> {
+    let patterns = keyword_patterns(keywords)?;
+
+    let mut contents = read_contents(file_paths, max_total_chars, source, warnings);
+    // Truncate first, so the markers don't count against the limit and can't be cut in half,
+    // but note the truncation after highlighting, so that keywords in the notice aren't marked
+    let notices = match max_total_chars {
+        Some(max_total_chars) => contents.truncate(max_total_chars),
+        None => Vec::new(),
+    };
+    for (_, content) in contents.files.iter_mut() {
+        if let FileContent::Text(text) = content {
+            *text = highlight(text, &patterns, open, close);
         }
     }
+    contents.append_notices(notices);
 
     Ok(serde_json::to_string_pretty(&contents)?)
 }
 
+/// Returns whether a quote appears in the retrieved contents, ignoring differences in whitespace
+/// and trailing punctuation. Escapes in the JSON contents are undone for the comparison.
+pub fn quote_in_context(quote: &str, contents: &str) -> bool {
+    let plain = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let quote = plain(quote.trim_end_matches(['.', ',', ';', ':', '!', '?']));
+    if quote.is_empty() {
+        return false;
+    }
+    let contents = contents
+        .replace("\\n", " ")
+        .replace("\\t", " ")
+        .replace("\\\"", "\"")
+        .replace("\\\\", "\\");
+    plain(&contents).contains(&quote)
+}
+
+/// Puts extra text, such as standing instructions, before the retrieved contents
+/// The result is passed to the model as a whole, so the extra text counts against its context length
+pub fn prepend_context(extra: &[String], contents: &str) -> String {
+    if extra.is_empty() {
+        return contents.to_string();
+    }
+    format!("{}\n\n{}", extra.join("\n\n"), contents)
+}
+
+/// Returns the part of a file under the heading with the given title, matched case-insensitively
+/// The section ends at the next heading of the same or a higher level, or at any heading
+/// if `include_subheadings` is false. The file is read through `source`.
+pub fn get_section(path: &str, heading: &str, include_subheadings: bool, source: &dyn KnowledgeSource) -> Result<String> {
+    let bytes = source
+        .read_file(Path::new(path))
+        .with_context(|| format!("Failed to read file: {}", path))?
+        .ok_or_else(|| anyhow::anyhow!("File not found: {}", path))?;
+    let content = String::from_utf8(bytes.to_vec()).with_context(|| format!("Failed to read file: {}", path))?;
+    let content = normalize_text(content);
+    let parser = parser_for(Path::new(path));
+
+    let wanted = heading.trim().to_lowercase();
+    let mut lines = content.lines();
+    let level = lines
+        .by_ref()
+        .find_map(|line| {
+            parser
+                .heading(line)
+                .filter(|(_, title)| title.to_lowercase() == wanted)
+                .map(|(level, _)| (level, line))
+        });
+    let Some((level, heading_line)) = level else {
+        return Err(anyhow::anyhow!("Heading not found in {}: {}", path, heading));
+    };
+
+    let mut section = vec![heading_line];
+    for line in lines {
+        if let Some((next_level, _)) = parser.heading(line) {
+            if next_level <= level || !include_subheadings {
+                break;
+            }
+        }
+        section.push(line);
+    }
+
+    Ok(section.join("\n"))
+}
+
+/// Returns up to `max_lines` non-empty lines from a reader, for a preview of a file
+pub fn preview_lines(reader: impl BufRead, max_lines: usize) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    for line in reader.split(b'\n') {
+        if lines.len() >= max_lines {
+            break;
+        }
+        let line = String::from_utf8_lossy(&line?).trim_start_matches('\u{feff}').trim_end().to_string();
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+
+    Ok(lines)
+}
+
+/// Wraps every match of the patterns in the given markers
+fn highlight(text: &str, patterns: &[regex::Regex], open: &str, close: &str) -> String {
+    // Collect match ranges from all patterns and merge overlapping ones
+    let mut ranges: Vec<(usize, usize)> = patterns
+        .iter()
+        .flat_map(|p| p.find_iter(text).map(|m| (m.start(), m.end())))
+        .collect();
+    ranges.sort();
+
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut pos = 0;
+    for (start, end) in merged {
+        result.push_str(&text[pos..start]);
+        result.push_str(open);
+        result.push_str(&text[start..end]);
+        result.push_str(close);
+        pos = end;
+    }
+    result.push_str(&text[pos..]);
+    result
+}
+
+/// Reads the specified files from the knowledge source, in order and skipping duplicates
+/// With `max_chars`, text files are only read as far as that many characters can be kept.
+/// Files that can't be read are reported to `warnings` and stand in with an error message.
+fn read_contents(
+    file_paths: &[String],
+    max_chars: Option<usize>,
+    source: &dyn KnowledgeSource,
+    warnings: &Warnings,
+) -> Contents {
+    let mut contents = Vec::new();
+    let mut partial = HashSet::new();
+    let mut seen = HashSet::new();
+
+    for path in file_paths {
+        if !seen.insert(path) {
+            continue;
+        }
+
+        let read = match max_chars {
+            Some(max_chars) => read_text_prefix(Path::new(path), max_chars, source),
+            None => source.read_file(Path::new(path)).map(|bytes| bytes.map(|bytes| (bytes.to_vec(), true))),
+        };
+        match read {
+            Ok(Some((bytes, complete))) => {
+                if !complete {
+                    partial.insert(path.clone());
+                }
+                contents.push((path.clone(), FileContent::from_bytes(bytes)));
+            }
+            Ok(None) => {
+                contents.push((path.clone(), FileContent::Text("File not found".to_string())));
+            }
+            Err(e) => {
+                warnings.warn(format_args!("Error reading file {}: {}", path, e));
+                contents.push((path.clone(), FileContent::Text(format!("Error reading file: {}", e))));
+            }
+        }
+    }
+
+    Contents { files: contents, partial }
+}
+
+/// Reads enough of a file to fill `max_chars` characters of text, returning whether it was read whole
+/// Files that turn out to be binary are read whole, since binary contents aren't truncated.
+fn read_text_prefix(path: &Path, max_chars: usize, source: &dyn KnowledgeSource) -> Result<Option<(Vec<u8>, bool)>> {
+    // A character takes at most 4 bytes, after a 3-byte byte order mark. One character
+    // more than the cap makes sure a file read in part is always marked as truncated.
+    let max_bytes = max_chars.saturating_add(1).saturating_mul(4).saturating_add(3);
+    let Some((bytes, complete)) = source.read_file_prefix(path, max_bytes)? else {
+        return Ok(None);
+    };
+    if complete {
+        return Ok(Some((bytes.to_vec(), true)));
+    }
+
+    // A character cut off at the limit isn't a sign of binary content, so drop it
+    let valid = match std::str::from_utf8(&bytes) {
+        Ok(text) => Some(text.len()),
+        Err(e) if e.error_len().is_none() => Some(e.valid_up_to()),
+        Err(_) => None,
+    };
+    match valid.filter(|&valid| !bytes[..valid].contains(&0)) {
+        Some(valid) => Ok(Some((bytes[..valid].to_vec(), false))),
+        None => Ok(source.read_file(path)?.map(|bytes| (bytes.to_vec(), true))),
+    }
+}
+
+/// Locates each file's section in the output of `get_contents`
+/// Returns the character range of every file's entry, from its key up to the next file's key
+pub fn file_sections(contents: &str, file_paths: &[String]) -> Vec<(String, Range<usize>)> {
+    // Keys can be found verbatim since quotes inside content values are always escaped
+    let mut starts: Vec<(String, usize)> = Vec::new();
+    let mut search_from = 0;
+    for path in file_paths {
+        let Ok(key) = serde_json::to_string(path) else { continue };
+        if let Some(offset) = contents[search_from..].find(&format!("{}: ", key)) {
+            let byte_offset = search_from + offset;
+            starts.push((path.clone(), byte_offset));
+            search_from = byte_offset + key.len();
+        }
+    }
+
+    let char_index = |byte_offset: usize| contents[..byte_offset].chars().count();
+    let end = contents.chars().count();
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, (path, start))| {
+            let section_end = starts.get(i + 1).map_or(end, |(_, next)| char_index(*next));
+            (path.clone(), char_index(*start)..section_end)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::File;
+    use crate::source::FileSystemSource;
+    use std::fs::{self, File};
     use std::io::Write as IoWrite;
     use tempfile::tempdir;
 
@@ -48,7 +485,7 @@ mod tests {
         
         // Test with existing file
         let file_paths = vec![test_file_path.to_string_lossy().to_string()];
-        let result = get_contents(&file_paths).unwrap();
+        let result = get_contents(&file_paths, None, &FileSystemSource::default(), &Warnings::default()).unwrap();
         
         // Result should be a JSON string containing our test content
         assert!(result.contains("test_content.txt"));
@@ -56,7 +493,7 @@ mod tests {
         
         // Test with non-existent file
         let file_paths = vec!["nonexistent_file.txt".to_string()];
-        let result = get_contents(&file_paths).unwrap();
+        let result = get_contents(&file_paths, None, &FileSystemSource::default(), &Warnings::default()).unwrap();
         
         // Result should indicate file not found
         assert!(result.contains("nonexistent_file.txt"));
@@ -65,4 +502,274 @@ mod tests {
         // Clean up
         drop(temp_dir);
     }
+
+    #[test]
+    fn test_get_contents_keeps_order() {
+        let temp_dir = tempdir().unwrap();
+        let file_paths: Vec<String> = ["b.org", "a.org", "c.org", "a.org"]
+            .iter()
+            .map(|name| {
+                let path = temp_dir.path().join(name);
+                fs::write(&path, format!("content of {}", name)).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+
+        let result = get_contents(&file_paths, None, &FileSystemSource::default(), &Warnings::default()).unwrap();
+        let sections = file_sections(&result, &file_paths[..3]);
+
+        assert_eq!(sections.len(), 3);
+        for (i, (path, range)) in sections.iter().enumerate() {
+            assert_eq!(path, &file_paths[i]);
+            let section: String = result.chars().skip(range.start).take(range.len()).collect();
+            assert!(section.starts_with(&serde_json::to_string(path).unwrap()));
+        }
+        assert!(sections[0].1.end == sections[1].1.start);
+        assert_eq!(result.matches("a.org").count(), 2);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_preview_lines() {
+        let lines = preview_lines("* Heading\n\nfirst\nsecond\nthird\n".as_bytes(), 3).unwrap();
+        assert_eq!(lines, vec!["* Heading", "first", "second"]);
+    }
+
+    #[test]
+    fn test_get_contents_binary() {
+        let temp_dir = tempdir().unwrap();
+        let test_file_path = temp_dir.path().join("image.png");
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00];
+        fs::write(&test_file_path, png).unwrap();
+
+        let file_paths = vec![test_file_path.to_string_lossy().to_string()];
+        let result = get_contents(&file_paths, None, &FileSystemSource::default(), &Warnings::default()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&result).unwrap();
+        let content = &json[file_paths[0].as_str()];
+
+        assert_eq!(content["mime"], "image/png");
+        assert_eq!(content["base64"], BASE64.encode(png));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_get_contents_read_error() {
+        let temp_dir = tempdir().unwrap();
+        let file_paths = vec![temp_dir.path().to_string_lossy().to_string()];
+
+        // A file that can't be read is reported as a warning, which a quiet run doesn't print
+        let warnings = Warnings::new(true);
+        let result = get_contents(&file_paths, None, &FileSystemSource::default(), &warnings).unwrap();
+        assert!(result.contains("Error reading file"));
+        let messages = warnings.take();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Error reading file"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_get_outline() {
+        let temp_dir = tempdir().unwrap();
+        let org = temp_dir.path().join("notes.org");
+        fs::write(&org, "#+TITLE: Notes\n* Intro\n\nhello\nmore\n** Details\ndeep\n").unwrap();
+        let txt = temp_dir.path().join("plain.txt");
+        fs::write(&txt, "\nFirst line\nSecond line\n").unwrap();
+        let file_paths = vec![org.to_string_lossy().to_string(), txt.to_string_lossy().to_string()];
+
+        let result = get_outline(&file_paths, false, None, &FileSystemSource::default(), &Warnings::default()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(json[&file_paths[0]], "* Intro\n** Details");
+        assert_eq!(json[&file_paths[1]], "First line");
+
+        let result = get_outline(&file_paths, true, None, &FileSystemSource::default(), &Warnings::default()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(json[&file_paths[0]], "* Intro\nhello\n** Details\ndeep");
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_get_section() {
+        let temp_dir = tempdir().unwrap();
+        let org = temp_dir.path().join("notes.org");
+        fs::write(&org, "* Intro\nhi\n* Installation  :setup:\nrun it\n** Linux\napt\n* Usage\n").unwrap();
+        let md = temp_dir.path().join("notes.md");
+        fs::write(&md, "# Intro\n## Installation\nrun it\n### Linux\napt\n## Usage\n").unwrap();
+
+        for path in [&org, &md] {
+            let path = path.to_string_lossy();
+            let section = get_section(&path, "installation", true, &FileSystemSource::default()).unwrap();
+            assert!(section.contains("run it") && section.contains("apt"));
+            assert!(!section.contains("Usage") && !section.contains("hi"));
+
+            let section = get_section(&path, "Installation", false, &FileSystemSource::default()).unwrap();
+            assert!(section.contains("run it") && !section.contains("Linux"));
+
+            assert!(get_section(&path, "Missing", true, &FileSystemSource::default()).is_err());
+        }
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_get_section_non_ascii() {
+        let temp_dir = tempdir().unwrap();
+        let org = temp_dir.path().join("notes.org");
+        fs::write(&org, "* ÜBERBLICK\ninhalt\n* Weiteres\n").unwrap();
+
+        let section = get_section(&org.to_string_lossy(), "Überblick", true, &FileSystemSource::default()).unwrap();
+        assert_eq!(section, "* ÜBERBLICK\ninhalt");
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_windows_text() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("windows.org");
+        fs::write(&path, "\u{feff}* Install\r\nrun it\r\n* Usage\r\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let result = get_contents(std::slice::from_ref(&path), None, &FileSystemSource::default(), &Warnings::default()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(json[path.as_str()], "* Install\nrun it\n* Usage\n");
+
+        assert_eq!(get_section(&path, "Install", true, &FileSystemSource::default()).unwrap(), "* Install\nrun it");
+        assert_eq!(preview_lines(fs::read(&path).unwrap().as_slice(), 1).unwrap(), vec!["* Install"]);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_prepend_context() {
+        let extra = vec!["Answer concisely.".to_string(), "Use bullet points.".to_string()];
+
+        assert_eq!(prepend_context(&extra, "{}"), "Answer concisely.\n\nUse bullet points.\n\n{}");
+        assert_eq!(prepend_context(&[], "{}"), "{}");
+    }
+
+    #[test]
+    fn test_get_contents_capped() {
+        let temp_dir = tempdir().unwrap();
+        let file_paths: Vec<String> = [("short.org", 4), ("long.org", 30), ("longer.org", 50)]
+            .iter()
+            .map(|(name, len)| {
+                let path = temp_dir.path().join(name);
+                fs::write(&path, "あ".repeat(*len)).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+
+        let result = get_contents(&file_paths, Some(24), &FileSystemSource::default(), &Warnings::default()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&result).unwrap();
+
+        assert_eq!(json[file_paths[0].as_str()], "あ".repeat(4));
+        assert_eq!(json[file_paths[1].as_str()], format!("{}... [truncated, 20 more chars]", "あ".repeat(10)));
+        // The longest file is only read up to the cap, so the rest of it isn't counted
+        assert_eq!(json[file_paths[2].as_str()], format!("{}... [truncated, at least 24 more chars]", "あ".repeat(10)));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_get_contents_read_partially() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("export.org");
+        fs::write(&path, "é".repeat(10_000)).unwrap();
+        let source = FileSystemSource::default();
+
+        // Only the bytes that can make up the capped text are read
+        let (bytes, complete) = source.read_file_prefix(&path, 100).unwrap().unwrap();
+        assert_eq!(bytes.len(), 100);
+        assert!(!complete);
+
+        // A cut-off character at the limit is dropped rather than making the file look binary
+        let file_paths = vec![path.to_string_lossy().to_string()];
+        let result = get_contents(&file_paths, Some(5), &source, &Warnings::default()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(json[file_paths[0].as_str()], "ééééé... [truncated, at least 8 more chars]");
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_get_bundle() {
+        let temp_dir = tempdir().unwrap();
+        let notes = temp_dir.path().join("notes.md");
+        let image = temp_dir.path().join("diagram.png");
+        fs::write(&notes, "# Notes\n\n```rust\nfn main() {}\n```\n").unwrap();
+        fs::write(&image, [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0]).unwrap();
+        let file_paths = vec![notes.to_string_lossy().to_string(), image.to_string_lossy().to_string()];
+
+        let bundle = get_bundle("rust", &file_paths, None, &FileSystemSource::default(), &Warnings::default()).unwrap();
+
+        assert!(bundle.starts_with("# rust\n\n## Contents\n\n"));
+        assert!(bundle.contains(&format!("1. [{}](#file-1)\n2. [{}](#file-2)\n", file_paths[0], file_paths[1])));
+        // The fence is longer than the fences inside the file
+        assert!(bundle.contains(&format!("## {}\n\n````md\n# Notes\n\n```rust\nfn main() {{}}\n```\n````\n", file_paths[0])));
+        assert!(bundle.contains("Binary file (image/png), not included."));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_quote_in_context() {
+        let contents = serde_json::to_string_pretty(&serde_json::json!({
+            "a.org": "* Tokio\nThe scheduler \"steals\" tasks\nfrom other threads."
+        }))
+        .unwrap();
+
+        // Line breaks and escaped quotes in the contents don't prevent a match
+        assert!(quote_in_context("The scheduler \"steals\" tasks from other threads.", &contents));
+        assert!(quote_in_context("  tasks   from other ", &contents));
+        assert!(!quote_in_context("The scheduler never steals tasks", &contents));
+        assert!(!quote_in_context("...", &contents));
+    }
+
+    #[test]
+    fn test_highlight_keywords() {
+        let keywords = vec!["rust".to_string()];
+        let highlighted = highlight_keywords("Rust and rust", &keywords, "\x1b[1m", "\x1b[0m").unwrap();
+
+        assert_eq!(highlighted, "\x1b[1mRust\x1b[0m and \x1b[1mrust\x1b[0m");
+    }
+
+    #[test]
+    fn test_get_contents_highlighted() {
+        let temp_dir = tempdir().unwrap();
+        let test_file_path = temp_dir.path().join("notes.org");
+        fs::write(&test_file_path, "Rust ownership and borrowing in rust").unwrap();
+
+        let file_paths = vec![test_file_path.to_string_lossy().to_string()];
+        let keywords = vec!["rust".to_string(), "own".to_string(), "ownership".to_string()];
+        let result = get_contents_highlighted(&file_paths, &keywords, "<mark>", "</mark>", None, &FileSystemSource::default(), &Warnings::default()).unwrap();
+
+        assert!(result.contains("<mark>Rust</mark> <mark>ownership</mark> and borrowing in <mark>rust</mark>"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_get_contents_highlighted_capped() {
+        let temp_dir = tempdir().unwrap();
+        let test_file_path = temp_dir.path().join("notes.org");
+        fs::write(&test_file_path, "rust rust rust rust").unwrap();
+
+        let file_paths = vec![test_file_path.to_string_lossy().to_string()];
+        let keywords = vec!["rust".to_string()];
+        let result = get_contents_highlighted(&file_paths, &keywords, "<mark>", "</mark>", Some(9), &FileSystemSource::default(), &Warnings::default()).unwrap();
+
+        // The markers don't count against the limit and aren't cut off
+        assert!(result.contains("<mark>rust</mark> <mark>rust</mark>... [truncated, 10 more chars]"));
+
+        // Keywords in the truncation notice itself aren't highlighted
+        let keywords = vec!["rust".to_string(), "more".to_string(), "chars".to_string()];
+        let result = get_contents_highlighted(&file_paths, &keywords, "<mark>", "</mark>", Some(9), &FileSystemSource::default(), &Warnings::default()).unwrap();
+        assert!(result.contains("... [truncated, 10 more chars]"));
+
+        drop(temp_dir);
+    }
 }
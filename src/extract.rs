@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+
+use crate::search::SearchTerm;
+
+/// Common English words that carry no meaning as search terms
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "all", "am", "an", "and", "any", "are", "as", "at",
+    "be", "because", "been", "before", "being", "below", "between", "both", "but", "by", "can",
+    "could", "did", "do", "does", "doing", "down", "during", "each", "few", "for", "from",
+    "further", "had", "has", "have", "having", "he", "her", "here", "hers", "him", "his", "how",
+    "i", "if", "in", "into", "is", "it", "its", "just", "know", "me", "more", "most", "my", "no",
+    "nor", "not", "now", "of", "off", "on", "once", "only", "or", "other", "our", "ours", "out",
+    "over", "own", "same", "she", "should", "so", "some", "such", "tell", "than", "that", "the",
+    "their", "them", "then", "there", "these", "they", "this", "those", "through", "to", "too",
+    "under", "until", "up", "very", "was", "we", "were", "what", "when", "where", "which",
+    "while", "who", "whom", "why", "will", "with", "would", "you", "your", "yours",
+];
+
+/// Derives search terms from a query without a model, by dropping stop words
+/// Words are lowercased, reduced to their stem if `stem` is set, and each is returned once
+/// in the order it first appears
+pub fn local_terms(text: &str, stem: bool) -> Vec<SearchTerm> {
+    let mut seen = HashSet::new();
+
+    text.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+        .map(|word| word.trim_matches(['-', '_']).to_lowercase())
+        .filter(|word| word.chars().count() > 1 && !STOP_WORDS.contains(&word.as_str()))
+        .map(|word| if stem { stem_word(&word) } else { word })
+        .filter(|word| seen.insert(word.clone()))
+        .map(|word| SearchTerm::direct(&word))
+        .collect()
+}
+
+/// Strips common English inflections from a word, leaving a stem of at least three letters
+/// Keywords match anywhere in a word, so the stem finds the other inflected forms too:
+/// "searching" becomes "search", which matches "searches" and "searched".
+fn stem_word(word: &str) -> String {
+    if !word.chars().all(|c| c.is_ascii_lowercase()) {
+        return word.to_string();
+    }
+
+    let stem = if let Some(stem) = word.strip_suffix("ies") {
+        stem
+    } else if let Some(stem) = word.strip_suffix("ing").or_else(|| word.strip_suffix("ed")) {
+        // A consonant doubled before the suffix, as in "running", is undoubled
+        match stem.as_bytes() {
+            [.., a, b] if a == b && !b"aeiouls".contains(b) => &stem[..stem.len() - 1],
+            _ => stem,
+        }
+    } else if let Some(stem) = word.strip_suffix("es").filter(|stem| {
+        ["s", "x", "z", "ch", "sh"].iter().any(|ending| stem.ends_with(ending))
+    }) {
+        stem
+    } else if word.ends_with('s') && !["ss", "us", "is"].iter().any(|ending| word.ends_with(ending)) {
+        &word[..word.len() - 1]
+    } else {
+        word
+    };
+
+    if stem.len() >= 3 { stem } else { word }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_terms() {
+        let terms = local_terms("What are the key features of my Rust project? Rust's async-await, too.", false);
+
+        assert_eq!(
+            SearchTerm::flatten(&terms),
+            vec!["key", "features", "rust", "project", "async-await"]
+        );
+    }
+
+    #[test]
+    fn test_local_terms_stemmed() {
+        let terms = local_terms("Searching notes on running features and libraries, or the feature list", true);
+
+        // Forms of a word share one stem
+        assert_eq!(
+            SearchTerm::flatten(&terms),
+            vec!["search", "note", "run", "feature", "librar", "list"]
+        );
+    }
+
+    #[test]
+    fn test_stem_word() {
+        assert_eq!(stem_word("boxes"), "box");
+        assert_eq!(stem_word("configured"), "configur");
+        assert_eq!(stem_word("stopped"), "stop");
+        assert_eq!(stem_word("class"), "class");
+        assert_eq!(stem_word("status"), "status");
+        // Stems shorter than three letters and non-ASCII words are left alone
+        assert_eq!(stem_word("bed"), "bed");
+        assert_eq!(stem_word("cafés"), "cafés");
+    }
+}
@@ -0,0 +1,681 @@
+//! Brain Knowledge System - query a knowledge base of local files with LLM integration
+//!
+//! The [`Brain`] struct runs the same pipeline as the `brain` CLI:
+//! extract search terms, search files, retrieve their contents and generate an answer.
+
+pub mod cache;
+pub mod config;
+pub mod search;
+pub mod content;
+pub mod extract;
+pub mod ollama;
+pub mod org;
+pub mod parser;
+pub mod query;
+pub mod source;
+
+use anyhow::Result;
+use futures_util::future::join_all;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::config::{Config, ExtractMethod};
+use crate::content::{
+    file_sections, get_bundle, get_contents, get_contents_highlighted, get_outline, get_section, normalize_text,
+    preview_lines, quote_in_context,
+};
+use crate::extract::local_terms;
+use crate::ollama::{strip_delimited, OllamaClient};
+use crate::query::parse_query;
+use crate::search::{search_files_cancellable, SearchOutcome, SearchResult, SearchTerm};
+use crate::source::{source_for, KnowledgeSource};
+
+/// Bytes read from the start of a file per line of its preview
+const PREVIEW_BYTES_PER_LINE: usize = 1024;
+
+/// A generated answer
+#[derive(Debug, Clone)]
+pub struct Answer {
+    /// The answer, with thinking sections stripped if configured
+    pub response: String,
+    /// Unprocessed model output, present when thinking sections were stripped
+    pub raw_response: Option<String>,
+    /// The parsed answer object, present when a structured answer was requested and valid
+    pub structured: Option<serde_json::Value>,
+}
+
+/// A quote the model gave as evidence for a statement of its answer
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Evidence {
+    /// The quoted text
+    pub quote: String,
+    /// File the model attributed the quote to, if it cited a valid source number
+    pub source: Option<String>,
+    /// Whether the quote appears in the context; if not, the model may have made it up
+    pub found: bool,
+}
+
+/// Search results for explicit terms, with the contents of the matched files if requested
+#[derive(Debug)]
+pub struct Retrieval {
+    pub results: Vec<SearchResult>,
+    /// Contents of the matched files as passed to the model, present if requested
+    pub contents: Option<String>,
+}
+
+/// Warnings issued while running the pipeline, such as skipped knowledge base roots
+/// Each is printed to stderr unless quiet, and kept until taken with [`Warnings::take`].
+#[derive(Debug, Default)]
+pub struct Warnings {
+    quiet: bool,
+    messages: Mutex<Vec<String>>,
+}
+
+impl Warnings {
+    /// Creates an empty list, which doesn't print warnings if `quiet`
+    pub fn new(quiet: bool) -> Self {
+        Self { quiet, messages: Mutex::new(Vec::new()) }
+    }
+
+    /// Prints a warning to stderr, unless quiet, and keeps it for [`Warnings::take`]
+    pub fn warn(&self, message: impl std::fmt::Display) {
+        let message = message.to_string();
+        if !self.quiet {
+            eprintln!("Warning: {}", message);
+        }
+        self.messages.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(message);
+    }
+
+    /// Returns the warnings issued since the last call
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.messages.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+/// Entry point for embedding the brain pipeline in another program
+pub struct Brain {
+    config: Arc<Config>,
+    /// Created on first use, so that searching without the model works without a valid endpoint
+    ollama: OnceLock<OllamaClient>,
+    /// Where files are listed and read from, with a cache shared by the search and content stages
+    source: Arc<dyn KnowledgeSource>,
+    /// Warnings issued by this instance, see [`Brain::take_warnings`]
+    warnings: Arc<Warnings>,
+}
+
+impl Brain {
+    /// Creates a new instance from a configuration
+    pub fn new(config: Config) -> Result<Self> {
+        let source = source_for(&config)?;
+        Ok(Self {
+            config: Arc::new(config),
+            ollama: OnceLock::new(),
+            source,
+            warnings: Arc::new(Warnings::default()),
+        })
+    }
+
+    /// Suppresses the warnings this instance prints to stderr, e.g. for the CLI's --quiet
+    /// They are still kept for [`Brain::take_warnings`], and errors are still returned to the caller.
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.warnings = Arc::new(Warnings::new(quiet));
+        self
+    }
+
+    /// Issues a warning about this run, printed to stderr unless quiet
+    pub fn warn(&self, message: impl std::fmt::Display) {
+        self.warnings.warn(message);
+    }
+
+    /// Returns the warnings issued since the last call, e.g. to include them in a response
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    /// Returns the configuration in use
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the Ollama client, creating it on first use
+    /// Fails if the configured endpoint or headers are invalid.
+    pub fn ollama(&self) -> Result<&OllamaClient> {
+        if let Some(ollama) = self.ollama.get() {
+            return Ok(ollama);
+        }
+
+        let config = &self.config.ollama;
+        let ollama = OllamaClient::new(&config.endpoint, &config.model, config.max_context_length)?
+            .with_headers(config.auth_token.as_deref(), &config.headers)?
+            .with_truncation_strategy(config.truncation_strategy)
+            .with_term_separators(&self.config.prompts.term_separators)
+            .with_max_concurrency(config.max_concurrency)
+            .with_stop(&config.stop)
+            .with_answer_language(self.config.prompts.answer_language.as_deref());
+        Ok(self.ollama.get_or_init(|| ollama))
+    }
+
+    /// Extracts search terms from a query with the configured method
+    /// Field filters such as `tag:rust` are kept as scoped terms rather than sent to the model
+    pub async fn extract_terms(&self, query: &str) -> Result<Vec<SearchTerm>> {
+        let parsed = parse_query(query);
+        let mut terms = if parsed.text.is_empty() {
+            Vec::new()
+        } else {
+            match self.config.extract.method {
+                ExtractMethod::Llm => self.ollama()?.extract_search_terms(&parsed.text).await?,
+                ExtractMethod::Local => local_terms(&parsed.text, self.config.extract.stem),
+            }
+        };
+        terms.extend(parsed.clauses);
+        Ok(terms)
+    }
+
+    /// Returns the system prompt and the prompt `extract_terms` would send to the model
+    /// None if the model wouldn't be asked, because terms are extracted locally or the
+    /// query has only field clauses.
+    pub fn extraction_prompt(&self, query: &str) -> Option<(String, String)> {
+        let parsed = parse_query(query);
+        if parsed.text.is_empty() || self.config.extract.method == ExtractMethod::Local {
+            return None;
+        }
+        Some(OllamaClient::extract_prompt(&parsed.text))
+    }
+
+    /// Splits a query into search terms without using Ollama
+    pub fn split_terms(query: &str) -> Vec<SearchTerm> {
+        let parsed = parse_query(query);
+        let mut terms: Vec<SearchTerm> = parsed.text.split_whitespace().map(SearchTerm::direct).collect();
+        terms.extend(parsed.clauses);
+        terms
+    }
+
+    /// Searches the knowledge base for files matching the search terms
+    /// Dropping the returned future, e.g. on a timeout, stops the scan
+    pub async fn search(&self, terms: &[SearchTerm]) -> Result<SearchOutcome> {
+        self.search_cancellable(terms, Arc::new(AtomicBool::new(false))).await
+    }
+
+    /// Searches the knowledge base, stopping with an error once `cancel` is set
+    /// This lets a new query supersede one still in progress
+    pub async fn search_cancellable(&self, terms: &[SearchTerm], cancel: Arc<AtomicBool>) -> Result<SearchOutcome> {
+        self.search_limited(terms, self.config.knowledge.max_files, cancel).await
+    }
+
+    /// Searches for up to `limit` files on a blocking thread, setting `cancel` if the future is dropped
+    async fn search_limited(&self, terms: &[SearchTerm], limit: usize, cancel: Arc<AtomicBool>) -> Result<SearchOutcome> {
+        let config = Arc::clone(&self.config);
+        let source = Arc::clone(&self.source);
+        let warnings = Arc::clone(&self.warnings);
+        let terms = terms.to_vec();
+        let mut guard = CancelOnDrop(Some(Arc::clone(&cancel)));
+        let results = tokio::task::spawn_blocking(move || {
+            search_files_cancellable(&config, &terms, limit, &cancel, source.as_ref(), &warnings)
+        })
+        .await?;
+        guard.0 = None;
+        results
+    }
+
+    /// Searches for explicit terms and optionally retrieves the matched files' contents, without Ollama
+    /// The result only depends on the files and the configuration, so it suits regression tests
+    /// and benchmarks of ranking. [`Brain::split_terms`] derives terms from a query like `--no-extract`.
+    pub async fn retrieve(&self, terms: &[SearchTerm], with_contents: bool) -> Result<Retrieval> {
+        let results = self.search(terms).await?.results;
+        let contents = if with_contents {
+            let paths: Vec<String> = results.iter().map(|r| r.path.clone()).collect();
+            Some(self.contents(&paths, None).await?)
+        } else {
+            None
+        };
+        Ok(Retrieval { results, contents })
+    }
+
+    /// Searches the knowledge base for `rerank_candidates` files, then has the model rerank them
+    /// Each file's relevance is replaced by the model's rating from 0 to 1, and the best
+    /// `max_files` are kept. Files the model fails to rate are ranked last.
+    /// Up to `ollama.max_concurrency` candidates are rated at once.
+    /// Dropping the returned future, e.g. on a timeout, stops the candidate scan
+    pub async fn search_reranked(&self, query: &str, terms: &[SearchTerm]) -> Result<SearchOutcome> {
+        let limit = self.config.search.rerank_candidates.max(self.config.knowledge.max_files);
+        let SearchOutcome { results: candidates, candidates: listed } =
+            self.search_limited(terms, limit, Arc::new(AtomicBool::new(false))).await?;
+
+        // Rate the candidates concurrently, with at most ollama.max_concurrency requests in flight
+        let ollama = self.ollama()?;
+        let ratings = join_all(candidates.iter().map(|candidate| async move {
+            let excerpt = self.excerpt(&candidate.path, self.config.search.rerank_excerpt_chars).await;
+            ollama.rate_relevance(query, &excerpt).await
+        }))
+        .await;
+
+        let mut reranked = Vec::with_capacity(candidates.len());
+        for (candidate, rating) in candidates.into_iter().zip(ratings) {
+            let relevance = match rating {
+                Ok(score) => score,
+                Err(e) => {
+                    self.warn(format_args!("Could not rerank {}: {}", candidate.path, e));
+                    0.0
+                }
+            };
+            reranked.push(SearchResult { relevance, ..candidate });
+        }
+
+        // Sort stably, so that ties keep their keyword search order
+        reranked.sort_by(|a, b| b.relevance.total_cmp(&a.relevance));
+        reranked.truncate(self.config.knowledge.max_files);
+        for (i, result) in reranked.iter_mut().enumerate() {
+            result.rank = i + 1;
+        }
+        Ok(SearchOutcome { results: reranked, candidates: listed })
+    }
+
+    /// Returns whether the best search result reaches `generate_min_relevance`
+    /// The best result is the most relevant one, whatever order `search.sort` puts them in.
+    /// The floor is on the keyword score scale, so it means nothing for [`Brain::search_reranked`] results.
+    pub fn is_confident(&self, results: &[SearchResult]) -> bool {
+        let best = results.iter().map(|r| r.relevance).fold(0.0, f64::max);
+        best >= self.config.search.generate_min_relevance
+    }
+
+    /// Retrieves the contents of the given files, highlighting the keywords if any are given
+    pub async fn contents(&self, file_paths: &[String], highlight: Option<&[String]>) -> Result<String> {
+        let config = Arc::clone(&self.config);
+        let source = Arc::clone(&self.source);
+        let warnings = Arc::clone(&self.warnings);
+        let file_paths = file_paths.to_vec();
+        let keywords = highlight.map(|k| k.to_vec());
+        tokio::task::spawn_blocking(move || match keywords {
+            Some(keywords) => get_contents_highlighted(
+                &file_paths,
+                &keywords,
+                &config.content.highlight_open,
+                &config.content.highlight_close,
+                config.content.max_total_chars,
+                source.as_ref(),
+                &warnings,
+            ),
+            None => get_contents(&file_paths, config.content.max_total_chars, source.as_ref(), &warnings),
+        })
+        .await?
+    }
+
+    /// Retrieves the heading outline of the given files, with the first line under each heading if `lead`
+    pub async fn outline(&self, file_paths: &[String], lead: bool) -> Result<String> {
+        let config = Arc::clone(&self.config);
+        let source = Arc::clone(&self.source);
+        let warnings = Arc::clone(&self.warnings);
+        let file_paths = file_paths.to_vec();
+        tokio::task::spawn_blocking(move || {
+            get_outline(&file_paths, lead, config.content.max_total_chars, source.as_ref(), &warnings)
+        })
+        .await?
+    }
+
+    /// Merges the given files into one Markdown document with a table of contents, capped like `contents`
+    pub async fn bundle(&self, title: &str, file_paths: &[String]) -> Result<String> {
+        let config = Arc::clone(&self.config);
+        let source = Arc::clone(&self.source);
+        let warnings = Arc::clone(&self.warnings);
+        let title = title.to_string();
+        let file_paths = file_paths.to_vec();
+        tokio::task::spawn_blocking(move || {
+            get_bundle(&title, &file_paths, config.content.max_total_chars, source.as_ref(), &warnings)
+        })
+        .await?
+    }
+
+    /// Returns the part of a file under a heading, see [`get_section`]
+    pub async fn section(&self, path: &str, heading: &str, include_subheadings: bool) -> Result<String> {
+        let source = Arc::clone(&self.source);
+        let path = path.to_string();
+        let heading = heading.to_string();
+        tokio::task::spawn_blocking(move || get_section(&path, &heading, include_subheadings, source.as_ref())).await?
+    }
+
+    /// Reads up to `max_chars` characters from the start of a file, for the model to judge it by
+    async fn excerpt(&self, path: &str, max_chars: usize) -> String {
+        self.read_file(path)
+            .await
+            .map(|bytes| {
+                normalize_text(String::from_utf8_lossy(&bytes).into_owned())
+                    .chars()
+                    .take(max_chars)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the first non-empty lines of a file, for a preview
+    /// Only the start of the file is read, up to `PREVIEW_BYTES_PER_LINE` bytes per line.
+    pub async fn preview(&self, path: &str, max_lines: usize) -> Result<Vec<String>> {
+        let source = Arc::clone(&self.source);
+        let path = path.to_string();
+        let max_bytes = max_lines.max(1).saturating_mul(PREVIEW_BYTES_PER_LINE);
+        let (bytes, complete) = tokio::task::spawn_blocking(move || {
+            source
+                .read_file_prefix(Path::new(&path), max_bytes)?
+                .ok_or_else(|| anyhow::anyhow!("File not found: {}", path))
+        })
+        .await??;
+
+        // A line cut off at the limit is left out, unless it's the only one
+        let end = match bytes.iter().rposition(|&b| b == b'\n') {
+            Some(newline) if !complete => newline + 1,
+            _ => bytes.len(),
+        };
+        preview_lines(&bytes[..end], max_lines)
+    }
+
+    /// Reads a file from the knowledge source
+    async fn read_file(&self, path: &str) -> Result<Arc<Vec<u8>>> {
+        let source = Arc::clone(&self.source);
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || {
+            source
+                .read_file(Path::new(&path))?
+                .ok_or_else(|| anyhow::anyhow!("File not found: {}", path))
+        })
+        .await?
+    }
+
+    /// Returns the number of characters of the contents passed to the model and the number truncated
+    pub fn context_chars(&self, contents: &str) -> (usize, usize) {
+        let config = &self.config.ollama;
+        let total = contents.chars().count();
+        let kept: usize = OllamaClient::kept_ranges(total, config.max_context_length, config.truncation_strategy)
+            .iter()
+            .map(|range| range.len())
+            .sum();
+        (kept, total - kept)
+    }
+
+    /// Returns the files whose content survives truncation of the retrieved contents
+    pub fn used_files(&self, file_paths: &[String], contents: &str) -> Vec<String> {
+        let config = &self.config.ollama;
+        let kept = OllamaClient::kept_ranges(contents.chars().count(), config.max_context_length, config.truncation_strategy);
+        file_sections(contents, file_paths)
+            .into_iter()
+            .filter(|(_, section)| kept.iter().any(|r| r.start < section.end && section.start < r.end))
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    /// Generates an answer to the query from the retrieved contents
+    pub async fn answer(&self, query: &str, contents: &str) -> Result<Answer> {
+        let instruction = self.config.prompts.answer_instruction();
+        let raw_response = self.ollama()?.generate_response(query, contents, instruction).await?;
+        Ok(self.strip_thinking(raw_response))
+    }
+
+    /// Generates an answer as a JSON object with `answer` and `key_points`
+    /// `structured` is None if the model's output isn't such an object
+    pub async fn answer_structured(&self, query: &str, contents: &str) -> Result<Answer> {
+        let instruction = self.config.prompts.answer_instruction();
+        let raw_response = self.ollama()?.generate_structured_response(query, contents, instruction).await?;
+        let mut answer = self.strip_thinking(raw_response);
+        answer.structured = OllamaClient::parse_structured_answer(&answer.response);
+        Ok(answer)
+    }
+
+    /// Generates an answer that quotes the contents supporting each statement, and checks the quotes
+    /// `files` are the files in `contents`, which the model cites by their number from 1.
+    /// The check is a best-effort search of the contents, ignoring differences in whitespace.
+    pub async fn answer_with_evidence(&self, query: &str, contents: &str, files: &[String]) -> Result<(Answer, Vec<Evidence>)> {
+        let mut instruction = OllamaClient::evidence_instruction(files);
+        if let Some(style) = self.config.prompts.answer_instruction() {
+            instruction = format!("{} {}", style, instruction);
+        }
+        let raw_response = self.ollama()?.generate_response(query, contents, Some(&instruction)).await?;
+        let answer = self.strip_thinking(raw_response);
+
+        let evidence = OllamaClient::parse_quotes(&answer.response)
+            .into_iter()
+            .map(|(quote, source)| Evidence {
+                found: quote_in_context(&quote, contents),
+                source: source.checked_sub(1).and_then(|i| files.get(i)).cloned(),
+                quote,
+            })
+            .collect();
+        Ok((answer, evidence))
+    }
+
+    /// Strips the model's thinking sections if configured
+    fn strip_thinking(&self, raw_response: String) -> Answer {
+        if self.config.ollama.strip_thinking {
+            let [open, close] = &self.config.ollama.thinking_delimiters;
+            Answer {
+                response: strip_delimited(&raw_response, open, close),
+                raw_response: Some(raw_response),
+                structured: None,
+            }
+        } else {
+            Answer {
+                response: raw_response,
+                raw_response: None,
+                structured: None,
+            }
+        }
+    }
+
+    /// Rates from 0 to 1 how well an answer is supported by the retrieved contents
+    pub async fn grounding(&self, query: &str, contents: &str, answer: &str) -> Result<f64> {
+        self.ollama()?.rate_grounding(query, contents, answer).await
+    }
+}
+
+/// Sets a cancellation flag when dropped, so that blocking work stops once nothing awaits it
+/// Clearing the flag disarms it once the work has finished
+struct CancelOnDrop(Option<Arc<AtomicBool>>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(cancel) = &self.0 {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::create_test_config_for_tests;
+    use crate::search::Field;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_used_files() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        config.ollama.max_context_length = 200;
+        let brain = Brain::new(config).unwrap();
+
+        let file_paths: Vec<String> = ["first.org", "second.org"]
+            .iter()
+            .map(|name| {
+                let path = temp_dir.path().join(name);
+                std::fs::write(&path, "x".repeat(500)).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+        let contents = brain.contents(&file_paths, None).await.unwrap();
+
+        assert_eq!(brain.used_files(&file_paths, &contents), vec![file_paths[0].clone()]);
+
+        drop(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_excerpt_normalized() {
+        let temp_dir = tempdir().unwrap();
+        let brain = Brain::new(create_test_config_for_tests(temp_dir.path())).unwrap();
+        let path = temp_dir.path().join("notes.org");
+        std::fs::write(&path, "\u{feff}first\r\nsecond\r\n").unwrap();
+
+        assert_eq!(brain.excerpt(&path.to_string_lossy(), 8).await, "first\nse");
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_extraction_prompt() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        config.ollama.endpoint = "invalid:url:format".to_string();
+        let brain = Brain::new(config.clone()).unwrap();
+
+        // Field clauses are searched directly, so only the free text reaches the model
+        let (system, prompt) = brain.extraction_prompt("tag:rust How do lifetimes work?").unwrap();
+        assert!(system.contains("search term extraction"));
+        assert!(prompt.ends_with("How do lifetimes work?"));
+        assert!(!prompt.contains("tag:rust"));
+        assert!(brain.extraction_prompt("tag:rust").is_none());
+
+        config.extract.method = ExtractMethod::Local;
+        let brain = Brain::new(config).unwrap();
+        assert!(brain.extraction_prompt("How do lifetimes work?").is_none());
+
+        drop(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_extract_terms_local() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        config.extract.method = ExtractMethod::Local;
+        let brain = Brain::new(config).unwrap();
+
+        let terms = brain.extract_terms("tag:rust How do lifetimes work?").await.unwrap();
+
+        assert_eq!(
+            terms,
+            vec![
+                SearchTerm::direct("lifetimes"),
+                SearchTerm::direct("work"),
+                SearchTerm::scoped("rust", Field::Tag),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preview() {
+        let temp_dir = tempdir().unwrap();
+        let brain = Brain::new(create_test_config_for_tests(temp_dir.path())).unwrap();
+        let path = temp_dir.path().join("notes.org");
+        std::fs::write(&path, "* Title\n\nfirst line\nsecond line\n").unwrap();
+        let path = path.to_string_lossy();
+        assert_eq!(brain.preview(&path, 2).await.unwrap(), vec!["* Title", "first line"]);
+
+        // Only the start of a large file is read, and the line cut off there is left out
+        let large = temp_dir.path().join("large.org");
+        std::fs::write(&large, format!("* Title\n{}\n", "x".repeat(100_000))).unwrap();
+        assert_eq!(brain.preview(&large.to_string_lossy(), 2).await.unwrap(), vec!["* Title"]);
+
+        drop(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_search_cancellable() {
+        let temp_dir = tempdir().unwrap();
+        let brain = Brain::new(create_test_config_for_tests(temp_dir.path())).unwrap();
+        let terms = vec![SearchTerm::direct("test")];
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        assert!(brain.search_cancellable(&terms, Arc::clone(&cancel)).await.is_ok());
+        assert!(!cancel.load(Ordering::Relaxed));
+
+        cancel.store(true, Ordering::Relaxed);
+        assert!(brain.search_cancellable(&terms, cancel).await.is_err());
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_context_chars() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        config.ollama.max_context_length = 10;
+        let brain = Brain::new(config).unwrap();
+
+        assert_eq!(brain.context_chars("short"), (5, 0));
+        assert_eq!(brain.context_chars(&"あ".repeat(25)), (10, 15));
+    }
+
+    #[tokio::test]
+    async fn test_search_without_ollama() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("a.org"), "rust notes").unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        config.ollama.endpoint = "invalid:url:format".to_string();
+
+        // Searching never creates the Ollama client, so the bogus endpoint doesn't matter
+        let brain = Brain::new(config).unwrap();
+        let results = brain.search(&Brain::split_terms("rust")).await.unwrap().results;
+        assert_eq!(results.len(), 1);
+        assert!(brain.ollama().is_err());
+
+        drop(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_warnings_per_instance() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        let healthy = Brain::new(config.clone()).unwrap().with_quiet(true);
+        config.knowledge.roots = vec![crate::config::RootConfig {
+            path: temp_dir.path().join("unmounted").to_string_lossy().to_string(),
+            weight: 1.0,
+        }];
+        let skipping = Brain::new(config).unwrap().with_quiet(true);
+
+        // Each instance only reports its own warnings, and taking them clears them
+        let terms = Brain::split_terms("rust");
+        skipping.search(&terms).await.unwrap();
+        healthy.search(&terms).await.unwrap();
+        assert_eq!(skipping.take_warnings().len(), 1);
+        assert!(skipping.take_warnings().is_empty());
+        assert!(healthy.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_is_confident() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        let result = |relevance| SearchResult {
+            path: "a.org".to_string(),
+            relevance,
+            rank: 1,
+            used: None,
+            extension: Some("org".to_string()),
+        };
+
+        let brain = Brain::new(config.clone()).unwrap();
+        assert!(brain.is_confident(&[]));
+
+        config.search.generate_min_relevance = 2.0;
+        let brain = Brain::new(config).unwrap();
+        assert!(brain.is_confident(&[result(2.0), result(1.0)]));
+        assert!(!brain.is_confident(&[result(1.5)]));
+        assert!(!brain.is_confident(&[]));
+
+        // Results sorted by path or mtime may not put the best match first
+        assert!(brain.is_confident(&[result(1.0), result(3.0)]));
+    }
+
+    #[test]
+    fn test_split_terms() {
+        let terms = Brain::split_terms("tag:rust ownership  lifetimes");
+
+        assert_eq!(
+            terms,
+            vec![
+                SearchTerm::direct("ownership"),
+                SearchTerm::direct("lifetimes"),
+                SearchTerm::scoped("rust", Field::Tag),
+            ]
+        );
+    }
+}
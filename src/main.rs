@@ -1,78 +1,648 @@
-mod config;
-mod search;
-mod content;
-mod ollama;
-
-use anyhow::Result;
-use clap::{Parser, ValueEnum};
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use notify::{RecursiveMode, Watcher};
+use schemars::JsonSchema;
 use serde::Serialize;
-use std::path::PathBuf;
-
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use crate::config::{load_config, load_config_from_path};
-use crate::content::get_contents;
-use crate::ollama::OllamaClient;
-use crate::search::search_files;
+use brain::config::{
+    load_config, load_config_from_path, AnswerStyle, Config, ExtractMethod, LowRelevance, Mode, OutputFormat,
+    SortOrder, SourceKind,
+};
+use brain::content::{highlight_keywords, prepend_context};
+use brain::ollama;
+use brain::search::{self, SearchTerm};
+use brain::source::is_knowledge_file;
+use brain::{Brain, Evidence};
 
-/// Operation mode for the brain tool
-#[derive(ValueEnum, Clone, Debug)]
-enum Mode {
-    /// Only extract and display search terms
-    ExtractOnly,
-    /// Extract terms and find matching files
-    SearchOnly,
-    /// Complete workflow including response generation
-    GenerateResponse,
+/// When to color text output
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColorChoice {
+    /// Color when printing to a terminal and NO_COLOR is unset
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
 }
 
-/// Output format for the brain tool
-#[derive(ValueEnum, Clone, Debug)]
-enum OutputFormat {
-    /// Standard text output
-    Text,
-    /// JSON formatted output
-    Json,
+impl ColorChoice {
+    /// Returns whether stdout output should be colored
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
 }
 
+/// ANSI escape sequences wrapped around highlighted keywords (bold red)
+const COLOR_OPEN: &str = "\x1b[1;31m";
+const COLOR_CLOSE: &str = "\x1b[0m";
+
 /// Response structure for JSON output
-#[derive(Serialize)]
+#[derive(Serialize, Default, JsonSchema)]
 struct BrainResponse {
     query: String,
     search_terms: Vec<String>,
     matched_files: Vec<search::SearchResult>,
-    response: String,
+    /// The answer text, or with --structured the parsed answer object
+    response: ResponseBody,
+    /// Unprocessed model output, present when thinking sections were stripped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_response: Option<String>,
+    /// Matched files that survived context truncation, present when a response was generated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    used_files: Option<Vec<String>>,
+    /// How well the response is supported by the context, from 0 to 1, present with --grounding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grounding_score: Option<f64>,
+    /// Quotes the model gave to support its statements, present with --evidence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    evidence: Option<Vec<Evidence>>,
+    /// Characters of context passed to the model after truncation, present when a response was generated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context_chars: Option<usize>,
+    /// Characters cut from the context to fit max_context_length, present when a response was generated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context_truncated_chars: Option<usize>,
+    /// Whether the best match was below search.generate_min_relevance
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    low_confidence: bool,
+    /// Duration of each pipeline stage, present with --profile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<Timings>,
+    /// Warnings issued during the run, such as skipped knowledge base roots
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+/// Generated answer: plain text, or a JSON object with --structured
+#[derive(Serialize, JsonSchema)]
+#[serde(untagged)]
+enum ResponseBody {
+    Text(String),
+    Structured(serde_json::Value),
+}
+
+impl Default for ResponseBody {
+    fn default() -> Self {
+        ResponseBody::Text(String::new())
+    }
+}
+
+/// Wall-clock duration of each pipeline stage in milliseconds, for --profile
+#[derive(Serialize, Default, Clone, JsonSchema)]
+struct Timings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extract_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    search_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contents_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generate_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grounding_ms: Option<u128>,
+}
+
+impl Timings {
+    /// Prints the duration of each stage that ran to stderr
+    fn report(&self) {
+        let stages = [
+            ("extract", self.extract_ms),
+            ("search", self.search_ms),
+            ("contents", self.contents_ms),
+            ("generate", self.generate_ms),
+            ("grounding", self.grounding_ms),
+        ];
+        for (stage, ms) in stages {
+            if let Some(ms) = ms {
+                eprintln!("{:>9}: {} ms", stage, ms);
+            }
+        }
+    }
+}
+
+impl Args {
+    /// Whether progress messages are printed, which only happens in text output without --quiet
+    fn progress(&self) -> bool {
+        matches!(self.format, OutputFormat::Text) && !self.quiet
+    }
+}
+
+/// Reports the timings and returns them for the JSON response if --profile was given
+fn profile(args: &Args, timings: &Timings) -> Option<Timings> {
+    args.profile.then(|| {
+        timings.report();
+        timings.clone()
+    })
+}
+
+/// Matched file with a preview, for the list subcommand
+#[derive(Serialize)]
+struct ListEntry {
+    path: String,
+    relevance: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extension: Option<String>,
+    preview: Vec<String>,
+}
+
+/// Written document and the files in it, for the export subcommand
+#[derive(Serialize)]
+struct ExportEntry<'a> {
+    out: &'a Path,
+    files: Vec<String>,
+}
+
+/// Content under a heading, for the section subcommand
+#[derive(Serialize)]
+struct SectionEntry<'a> {
+    path: &'a str,
+    heading: &'a str,
+    content: String,
+}
+
+/// Prompt sent to the model to extract search terms, for --explain-prompt
+#[derive(Serialize)]
+struct PromptEntry {
+    system: String,
+    prompt: String,
+}
+
+/// Build information, for the version subcommand
+#[derive(Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    git_commit: Option<&'static str>,
+    build_date: &'static str,
+    rustc_version: Option<&'static str>,
+}
+
+impl VersionInfo {
+    /// Returns the information recorded by the build script, leaving out what it couldn't find
+    fn current() -> Self {
+        let non_empty = |value: &'static str| Some(value).filter(|v| !v.is_empty());
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: non_empty(env!("BRAIN_GIT_COMMIT")),
+            build_date: env!("BRAIN_BUILD_DATE"),
+            rustc_version: non_empty(env!("BRAIN_RUSTC_VERSION")),
+        }
+    }
+}
+
+/// Subcommands of the brain tool
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// List matched files with a short preview, without generating a response
+    List {
+        /// The query to process
+        query: String,
+        
+        /// Number of lines to show from each file
+        #[clap(long, default_value_t = 5)]
+        lines: usize,
+    },
+    
+    /// Write the matched files into one Markdown document with a table of contents
+    Export {
+        /// The query to process
+        query: String,
+        
+        /// File to write the document to
+        #[clap(long, value_name = "PATH")]
+        out: PathBuf,
+    },
+    
+    /// Print the part of a file under a heading, without generating a response
+    Section {
+        /// The file to read
+        path: String,
+        
+        /// Title of the heading, matched case-insensitively
+        heading: String,
+        
+        /// Stop at the first subheading instead of including the subheadings' content
+        #[clap(long)]
+        no_subheadings: bool,
+    },
+    
+    /// Print the JSON Schema of the --format json output
+    Schema,
+    
+    /// Print the version and build information
+    Version {
+        /// Print the information as JSON, like --format json
+        #[clap(long)]
+        json: bool,
+    },
+    
+    /// Inspect the configuration
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+/// Actions of the config subcommand
+#[derive(Subcommand, Clone, Debug)]
+enum ConfigAction {
+    /// Print the effective configuration, after CLI overrides, with secrets redacted
+    Show,
 }
 
 /// Brain Knowledge System - A CLI tool for querying your knowledge base
-#[derive(Parser, Debug)]
-#[clap(author, version, about)]
+#[derive(Parser, Clone, Debug)]
+#[clap(author, version, about, subcommand_negates_reqs = true)]
 struct Args {
-    /// The query to process
+    #[clap(subcommand)]
+    command: Option<Command>,
+    
+    /// The query to process. A query that is a subcommand name, like `list`, must follow `--`
     #[clap(required = true)]
-    query: String,
+    query: Option<String>,
     
-    /// Operation mode: extract-only, search-only, or generate-response
-    #[clap(long, value_enum, default_value_t = Mode::GenerateResponse)]
+    /// Operation mode: extract-only, search-only, or generate-response [default: cli.default_mode, or generate-response]
+    #[clap(long = "mode", id = "mode", value_enum)]
+    mode_flag: Option<Mode>,
+    
+    /// Mode in effect, from --mode or the configured default
+    #[clap(skip)]
     mode: Mode,
     
-    /// Output format: text or json
-    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    /// Output format: text or json [default: cli.default_format, or text]
+    #[clap(long = "format", id = "format", value_enum, global = true)]
+    format_flag: Option<OutputFormat>,
+    
+    /// Format in effect, from --format or the configured default
+    #[clap(skip)]
     format: OutputFormat,
     
     /// Override the maximum number of files to use
-    #[clap(long)]
+    #[clap(long, global = true)]
     max_files: Option<usize>,
     
-    /// Specify an alternative config file path
-    #[clap(long, value_parser)]
+    /// Keep at most this many results from any single directory, overriding search.max_per_dir
+    #[clap(long, value_name = "N", global = true)]
+    max_per_dir: Option<NonZeroUsize>,
+    
+    /// Override the maximum context length passed to the model
+    #[clap(long, global = true)]
+    max_context_length: Option<usize>,
+    
+    /// Specify an alternative config file path, instead of the nearest .brain.toml or the global config
+    #[clap(long, value_parser, global = true)]
     config: Option<PathBuf>,
+    
+    /// Also write the JSON output, of the query or of a subcommand, to this path, regardless of --format
+    #[clap(long, value_parser, global = true)]
+    emit_json: Option<PathBuf>,
+    
+    /// Emit JSON on a single line instead of pretty-printing it
+    #[clap(long, global = true)]
+    json_compact: bool,
+    
+    /// Highlight search term matches in the retrieved file contents
+    #[clap(long)]
+    highlight: bool,
+    
+    /// Pass only each file's headings to the model instead of its full content
+    #[clap(long, conflicts_with = "highlight")]
+    outline: bool,
+    
+    /// With --outline, also include the first line under each heading
+    #[clap(long, requires = "outline")]
+    outline_lead: bool,
+    
+    /// Length and format of the answer: concise, detailed, or bullets
+    #[clap(long, value_enum)]
+    answer_style: Option<AnswerStyle>,
+    
+    /// Language to answer in, e.g. English, instead of the language of the query
+    #[clap(long, value_name = "LANG")]
+    answer_language: Option<String>,
+    
+    /// Answer from these files instead of extracting terms and searching the knowledge base
+    #[clap(long, num_args = 1.., value_name = "PATH", conflicts_with = "mode")]
+    context_files: Vec<PathBuf>,
+    
+    /// Extra text, such as a standing instruction, to put before the retrieved contents (repeatable)
+    #[clap(long, value_name = "TEXT")]
+    append_context: Vec<String>,
+    
+    /// File whose text is put before the retrieved contents, like --append-context
+    #[clap(long, value_parser)]
+    context_file: Option<PathBuf>,
+    
+    /// Ask the model to rate how well the response is supported by the context (an extra call)
+    #[clap(long)]
+    grounding: bool,
+    
+    /// Ask for the answer as a JSON object with answer and key_points, using Ollama's JSON mode
+    #[clap(long)]
+    structured: bool,
+    
+    /// Have the model quote the notes supporting each statement, and flag quotes not found in them
+    #[clap(long, conflicts_with = "structured")]
+    evidence: bool,
+    
+    /// Skip LLM term extraction and search for the words of the query as-is
+    #[clap(long, global = true)]
+    no_extract: bool,
+    
+    /// Print the prompt sent to the model to extract search terms, without calling it
+    #[clap(long, conflicts_with_all = ["no_extract", "context_files"])]
+    explain_prompt: bool,
+    
+    /// Method of search term extraction: llm, or local to derive keywords without Ollama
+    #[clap(long, value_enum, global = true)]
+    extract: Option<ExtractMethod>,
+    
+    /// Leave out files mentioning this word, in addition to search.exclude_terms (repeatable)
+    #[clap(long, value_name = "WORD", global = true)]
+    exclude_term: Vec<String>,
+    
+    /// Have the model rerank the top search.rerank_candidates results by relevance to the query
+    #[clap(long)]
+    rerank: bool,
+    
+    /// Only match headings and file names, not file bodies
+    #[clap(long, global = true)]
+    titles_only: bool,
+    
+    /// When to highlight keywords in text output with colors: auto, always, or never
+    #[clap(long, value_enum, default_value_t = ColorChoice::Auto, global = true)]
+    color: ColorChoice,
+    
+    /// Order of the search results: relevance, path, or mtime
+    #[clap(long, value_enum, global = true)]
+    sort: Option<SortOrder>,
+    
+    /// Print the duration of each pipeline stage to stderr
+    #[clap(long, global = true)]
+    profile: bool,
+    
+    /// Print only the results, without progress messages or warnings; errors are still reported
+    #[clap(short, long, global = true)]
+    quiet: bool,
+    
+    /// Abort the whole run if it takes longer than this many seconds
+    #[clap(long, global = true)]
+    timeout: Option<u64>,
+    
+    /// Exit with code 2, or --fail-on-empty=CODE, instead of 0 when no file matches the search
+    #[clap(
+        long,
+        value_name = "CODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "2",
+        value_parser = clap::value_parser!(i32).range(1..=255),
+        global = true
+    )]
+    fail_on_empty: Option<i32>,
+    
+    /// Keep running, and run again whenever a searched file changes, until interrupted
+    #[clap(long, global = true, conflicts_with_all = ["timeout", "fail_on_empty", "explain_prompt"])]
+    watch: bool,
 }
 
-async fn run() -> Result<()> {
-    // Parse CLI arguments
-    let args = Args::parse();
+/// Grounding score below which the response is flagged as possibly speculative
+const LOW_GROUNDING_SCORE: f64 = 0.5;
+
+/// Exit code used when the --timeout limit is exceeded, as in timeout(1)
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Exit code used when interrupted with Ctrl-C, following the 128 + SIGINT convention
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Runs the pipeline under the --timeout limit, if one was given
+async fn run_with_timeout(args: Args) -> Result<()> {
+    match args.timeout {
+        Some(secs) => match tokio::time::timeout(Duration::from_secs(secs), run(args)).await {
+            Ok(result) => result,
+            Err(_) => {
+                eprintln!("Error: Timed out after {} seconds", secs);
+                exit_with(TIMEOUT_EXIT_CODE);
+            }
+        },
+        None => run(args).await,
+    }
+}
+
+/// Flushes the output printed so far and exits with the given code
+fn exit_with(code: i32) -> ! {
+    let _ = io::stdout().flush();
+    std::process::exit(code);
+}
+
+/// Serializes a JSON response, compact or pretty-printed
+fn to_json<T: Serialize>(response: &T, compact: bool) -> Result<String> {
+    let json = if compact {
+        serde_json::to_string(response)?
+    } else {
+        serde_json::to_string_pretty(response)?
+    };
+    Ok(json)
+}
+
+/// Writes the JSON output to the --emit-json path, if one was given
+fn emit_json<T: Serialize>(path: Option<&Path>, response: &T, compact: bool) -> Result<()> {
+    if let Some(path) = path {
+        let json = to_json(response, compact)?;
+        fs::write(path, json)
+            .with_context(|| format!("Failed to write JSON output: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Lists matched files with a preview of their first lines
+async fn run_list(args: &Args, brain: &Brain, query: &str, lines: usize) -> Result<()> {
+    let search_terms = if args.no_extract {
+        Brain::split_terms(query)
+    } else {
+        brain.extract_terms(query).await?
+    };
+    let searched = brain.search(&search_terms).await?;
+    let empty_warning = empty_knowledge_base_warning(brain.config(), searched.candidates);
+    let search_results = searched.results;
+    if let Some(warning) = &empty_warning {
+        brain.warn(warning);
+    }
+    
+    let mut entries = Vec::with_capacity(search_results.len());
+    for result in search_results {
+        let preview = brain.preview(&result.path, lines).await.unwrap_or_default();
+        entries.push(ListEntry {
+            path: result.path,
+            relevance: result.relevance,
+            extension: result.extension,
+            preview,
+        });
+    }
+    
+    emit_json(args.emit_json.as_deref(), &entries, args.json_compact)?;
+    if matches!(args.format, OutputFormat::Json) {
+        println!("{}", to_json(&entries, args.json_compact)?);
+    } else if empty_warning.is_none() && !(args.quiet && entries.is_empty()) {
+        print_list(&entries, &SearchTerm::flatten_text(&search_terms), args.color.enabled())?;
+    }
+    
+    if let (true, Some(code)) = (entries.is_empty(), args.fail_on_empty) {
+        exit_with(code);
+    }
+    Ok(())
+}
+
+/// Describes the configuration if a search had no candidate files to search at all
+fn empty_knowledge_base_warning(config: &Config, candidates: usize) -> Option<String> {
+    if candidates != 0 {
+        return None;
+    }
+    let location = match config.knowledge.source {
+        SourceKind::Filesystem => {
+            let roots: Vec<&str> = config.knowledge.roots().into_iter().map(|(root, _)| root).collect();
+            format!("under {}", roots.join(", "))
+        }
+        SourceKind::Http => format!("at {}", config.knowledge.base_url.as_deref().unwrap_or_default()),
+    };
+    Some(format!(
+        "Knowledge base contains 0 matching files {} with extensions {}; check your config",
+        location,
+        config.knowledge.extensions.join(", ")
+    ))
+}
+
+/// Writes the matched files into one document at `out`
+async fn run_export(args: &Args, brain: &Brain, query: &str, out: &Path) -> Result<()> {
+    let search_terms = if args.no_extract {
+        Brain::split_terms(query)
+    } else {
+        brain.extract_terms(query).await?
+    };
+    let searched = brain.search(&search_terms).await?;
+    let search_results = searched.results;
+    if search_results.is_empty() {
+        match empty_knowledge_base_warning(brain.config(), searched.candidates) {
+            Some(warning) => brain.warn(warning),
+            None if args.progress() => println!("No matching files found."),
+            None => {}
+        }
+        if let Some(code) = args.fail_on_empty {
+            exit_with(code);
+        }
+        return Ok(());
+    }
     
-    // Load configuration
+    let files: Vec<String> = search_results.into_iter().map(|r| r.path).collect();
+    let bundle = brain.bundle(query, &files).await?;
+    fs::write(out, bundle).with_context(|| format!("Failed to write export: {}", out.display()))?;
+    
+    let entry = ExportEntry { out, files };
+    emit_json(args.emit_json.as_deref(), &entry, args.json_compact)?;
+    match args.format {
+        OutputFormat::Text if !args.quiet => println!("Exported {} files to {}", entry.files.len(), out.display()),
+        OutputFormat::Text => {}
+        OutputFormat::Json => println!("{}", to_json(&entry, args.json_compact)?),
+    }
+    Ok(())
+}
+
+/// Prints list entries as text, coloring keyword matches in the previews if `color` is set
+fn print_list(entries: &[ListEntry], keywords: &[String], color: bool) -> Result<()> {
+    if entries.is_empty() {
+        println!("No matching files found.");
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        println!(
+            "{}. {}{} (relevance: {:.2})",
+            i + 1,
+            search::file_label(entry.extension.as_deref()),
+            entry.path,
+            entry.relevance
+        );
+        for line in &entry.preview {
+            if color {
+                println!("    {}", highlight_keywords(line, keywords, COLOR_OPEN, COLOR_CLOSE)?);
+            } else {
+                println!("    {}", line);
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Time without further file events before a --watch run starts
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Runs repeatedly, whenever a searched file changes, until interrupted
+async fn watch(mut args: Args) -> Result<()> {
+    // The pipeline is kept between runs, so that e.g. the model's context window is only queried once
+    let brain = load_brain(&mut args)?;
+    let config = brain.config();
+    if config.knowledge.source != SourceKind::Filesystem {
+        return Err(anyhow::anyhow!("--watch only works with the filesystem knowledge source"));
+    }
+
+    // Forward file events from the watcher's thread to the async loop
+    let (sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let _ = sender.send(event);
+        }
+    })?;
+    for (root, _) in config.knowledge.roots() {
+        let root = fs::canonicalize(root).with_context(|| format!("Knowledge base path does not exist: {}", root))?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+    }
+
+    loop {
+        if let Err(e) = run_with(&args, &brain).await {
+            report_error(&e);
+        }
+        let _ = io::stdout().flush();
+
+        // Wait for a change to a searched file, ignoring reads such as our own
+        loop {
+            let event = events.recv().await.context("File watcher stopped")?;
+            let relevant = !event.kind.is_access()
+                && event.paths.iter().any(|path| is_knowledge_file(path, config).unwrap_or(false));
+            if relevant {
+                break;
+            }
+        }
+        // Let a burst of changes, such as an editor saving, settle before running again
+        while let Ok(Some(_)) = tokio::time::timeout(WATCH_DEBOUNCE, events.recv()).await {}
+        if !args.quiet {
+            eprintln!("\nKnowledge base changed, running again...\n");
+        }
+    }
+}
+
+/// Prints an error with its chain of causes to stderr
+fn report_error(e: &anyhow::Error) {
+    eprintln!("Error: {}", e);
+    
+    // Print cause chain for better error diagnostics
+    let mut cause = e.source();
+    while let Some(e) = cause {
+        eprintln!("Caused by: {}", e);
+        cause = e.source();
+    }
+}
+
+/// Loads the configuration and applies the overrides given as CLI arguments
+fn load_config_with_overrides(args: &Args) -> Result<Config> {
     let mut config = match &args.config {
         Some(config_path) => load_config_from_path(config_path)?,
         None => load_config()?,
@@ -83,99 +653,437 @@ async fn run() -> Result<()> {
         config.knowledge.max_files = max_files;
     }
     
-    // Initialize Ollama client
-    let ollama_client = OllamaClient::new(
-        &config.ollama.endpoint,
-        &config.ollama.model,
-        config.ollama.max_context_length,
-    )?;
+    // Override the per-directory result cap if specified in CLI args
+    if let Some(max_per_dir) = args.max_per_dir {
+        config.search.max_per_dir = Some(max_per_dir);
+    }
     
-    // Extract search terms from query
-    if matches!(args.format, OutputFormat::Text) {
-        println!("Extracting search terms from query...");
+    // Override max_context_length if specified in CLI args
+    if let Some(max_context_length) = args.max_context_length {
+        if max_context_length == 0 {
+            return Err(anyhow::anyhow!("--max-context-length must be greater than zero"));
+        }
+        config.ollama.max_context_length = max_context_length;
     }
-    let search_terms = ollama_client.extract_search_terms(&args.query).await?;
     
-    if matches!(args.format, OutputFormat::Text) {
-        println!("Search terms: {:?}", search_terms);
+    // Override the extraction method if specified in CLI args
+    if let Some(extract) = args.extract {
+        config.extract.method = extract;
     }
     
-    // If extract_only mode, output and stop here
-    if matches!(args.mode, Mode::ExtractOnly) {
-        if matches!(args.format, OutputFormat::Json) {
-            let response = BrainResponse {
-                query: args.query.clone(),
-                search_terms,
-                matched_files: vec![],
-                response: String::new(),
-            };
-            println!("{}", serde_json::to_string_pretty(&response)?);
+    // Add excluded terms if specified in CLI args
+    config.search.exclude_terms.extend(args.exclude_term.iter().cloned());
+    
+    // Restrict matching to headings and file names if specified in CLI args
+    if args.titles_only {
+        config.search.titles_only = true;
+    }
+    
+    // Override the answer style if specified in CLI args
+    if let Some(style) = args.answer_style {
+        config.prompts.answer_style = Some(style);
+    }
+    
+    // Override the answer language if specified in CLI args
+    if let Some(language) = &args.answer_language {
+        config.prompts.answer_language = Some(language.clone());
+    }
+    
+    // Override the result order if specified in CLI args
+    if let Some(sort) = args.sort {
+        config.search.sort = sort;
+    }
+    
+    Ok(config)
+}
+
+async fn run(mut args: Args) -> Result<()> {
+    // The schema doesn't depend on the configuration, so print it before loading one
+    if let Some(Command::Schema) = &args.command {
+        let schema = schemars::schema_for!(BrainResponse);
+        emit_json(args.emit_json.as_deref(), &schema, args.json_compact)?;
+        println!("{}", to_json(&schema, args.json_compact)?);
+        return Ok(());
+    }
+    if let Some(Command::Version { json }) = &args.command {
+        let info = VersionInfo::current();
+        emit_json(args.emit_json.as_deref(), &info, args.json_compact)?;
+        if *json || matches!(args.format_flag, Some(OutputFormat::Json)) {
+            println!("{}", to_json(&info, args.json_compact)?);
+        } else {
+            println!("brain {}", info.version);
+            if let Some(commit) = info.git_commit {
+                println!("commit: {}", commit);
+            }
+            println!("built: {}", info.build_date);
+            if let Some(rustc) = info.rustc_version {
+                println!("compiler: {}", rustc);
+            }
         }
         return Ok(());
     }
     
-    // Search files based on search terms
-    if matches!(args.format, OutputFormat::Text) {
-        println!("Searching files...");
-    }
-    let search_results = search_files(&config, &search_terms)?;
+    let brain = load_brain(&mut args)?;
+    run_with(&args, &brain).await
+}
+
+/// Loads the configuration and initializes the pipeline
+/// The configured mode and format are filled in where no flag was given.
+fn load_brain(args: &mut Args) -> Result<Brain> {
+    let config = load_config_with_overrides(args)?;
+    args.mode = args.mode_flag.or(config.cli.default_mode).unwrap_or_default();
+    args.format = args.format_flag.or(config.cli.default_format).unwrap_or_default();
+    Ok(Brain::new(config)?.with_quiet(args.quiet))
+}
+
+/// Runs a subcommand or the main workflow with an initialized pipeline
+async fn run_with(args: &Args, brain: &Brain) -> Result<()> {
+    let config = brain.config();
     
-    if search_results.is_empty() && matches!(args.format, OutputFormat::Text) {
-        println!("No matching files found.");
+    // Run a subcommand instead of the main workflow if one was given
+    match &args.command {
+        Some(Command::List { query, lines }) => return run_list(args, brain, query, *lines).await,
+        Some(Command::Export { query, out }) => return run_export(args, brain, query, out).await,
+        Some(Command::Section { path, heading, no_subheadings }) => {
+            let content = brain.section(path, heading, !no_subheadings).await?;
+            let entry = SectionEntry { path, heading, content };
+            emit_json(args.emit_json.as_deref(), &entry, args.json_compact)?;
+            match args.format {
+                OutputFormat::Text => println!("{}", entry.content),
+                OutputFormat::Json => println!("{}", to_json(&entry, args.json_compact)?),
+            }
+            return Ok(());
+        }
+        Some(Command::Config { action: ConfigAction::Show }) => {
+            let shown = config.redacted();
+            emit_json(args.emit_json.as_deref(), &shown, args.json_compact)?;
+            match args.format {
+                OutputFormat::Text => print!("{}", toml::to_string_pretty(&shown)?),
+                OutputFormat::Json => println!("{}", to_json(&shown, args.json_compact)?),
+            }
+            return Ok(());
+        }
+        Some(Command::Schema) | Some(Command::Version { .. }) | None => {}
     }
+    let query = args.query.clone().unwrap_or_default();
     
-    // Display search results in text mode
-    if matches!(args.format, OutputFormat::Text) {
-        println!("\nFound {} matching files:", search_results.len());
-        for (i, result) in search_results.iter().enumerate() {
-            println!("{}. {} (relevance: {:.2})", i + 1, result.path, result.relevance);
+    // Show the term extraction prompt instead of running the query
+    if args.explain_prompt {
+        let Some((system, prompt)) = brain.extraction_prompt(&query) else {
+            return Err(anyhow::anyhow!(
+                "No prompt is sent to extract search terms: extract.method is local or the query has only field clauses"
+            ));
+        };
+        match args.format {
+            OutputFormat::Text => println!("SYSTEM:\n{}\n\nPROMPT:\n{}", system, prompt),
+            OutputFormat::Json => println!("{}", to_json(&PromptEntry { system, prompt }, args.json_compact)?),
         }
+        return Ok(());
     }
     
-    // If search_only mode, output and stop here
-    if matches!(args.mode, Mode::SearchOnly) {
-        if matches!(args.format, OutputFormat::Json) {
+    let mut timings = Timings::default();
+    
+    // Answer from the given files, if any, without extracting terms or searching
+    let (search_terms, mut search_results) = if !args.context_files.is_empty() {
+        let results = args.context_files
+            .iter()
+            .enumerate()
+            .map(|(i, path)| {
+                let path = path.to_string_lossy().to_string();
+                search::SearchResult {
+                    extension: search::file_extension(&path),
+                    path,
+                    relevance: 0.0,
+                    rank: i + 1,
+                    used: None,
+                }
+            })
+            .collect();
+        (Vec::new(), results)
+    } else {
+        // Extract search terms from query, or use the query's words as they are
+        let start = Instant::now();
+        let search_terms = if args.no_extract {
+            Brain::split_terms(&query)
+        } else {
+            if args.progress() {
+                println!("Extracting search terms from query...");
+            }
+            brain.extract_terms(&query).await?
+        };
+        timings.extract_ms = Some(start.elapsed().as_millis());
+    
+        // With --quiet, the terms are only printed when they're the result
+        if matches!(args.format, OutputFormat::Text) && (!args.quiet || matches!(args.mode, Mode::ExtractOnly)) {
+            println!("Search terms: {:?}", SearchTerm::flatten(&search_terms));
+        }
+    
+        // If extract_only mode, output and stop here
+        if matches!(args.mode, Mode::ExtractOnly) {
             let response = BrainResponse {
-                query: args.query.clone(),
-                search_terms,
+                query: query.clone(),
+                search_terms: SearchTerm::flatten(&search_terms),
+                timings: profile(args, &timings),
+                warnings: brain.take_warnings(),
+                ..Default::default()
+            };
+            emit_json(args.emit_json.as_deref(), &response, args.json_compact)?;
+            if matches!(args.format, OutputFormat::Json) {
+                println!("{}", to_json(&response, args.json_compact)?);
+            }
+            return Ok(());
+        }
+    
+        // Search files based on search terms
+        if args.progress() {
+            println!("Searching files...");
+        }
+        let start = Instant::now();
+        let searched = if args.rerank {
+            brain.search_reranked(&query, &search_terms).await?
+        } else {
+            brain.search(&search_terms).await?
+        };
+        timings.search_ms = Some(start.elapsed().as_millis());
+        let empty_warning = empty_knowledge_base_warning(config, searched.candidates);
+        let search_results = searched.results;
+    
+        if search_results.is_empty() {
+            // An empty knowledge base is a configuration error, not a query without matches
+            if let Some(warning) = empty_warning {
+                brain.warn(warning);
+            } else if args.progress() {
+                println!("No matching files found.");
+            }
+        }
+    
+        // Display search results in text mode, with --quiet only when they're the result
+        if args.progress() {
+            println!("\nFound {} matching files:", search_results.len());
+        }
+        if matches!(args.format, OutputFormat::Text) && (!args.quiet || matches!(args.mode, Mode::SearchOnly)) {
+            for (i, result) in search_results.iter().enumerate() {
+                println!("{}. {}{} (relevance: {:.2})", i + 1, result.label(), result.path, result.relevance);
+            }
+        }
+    
+        // If search_only mode, or nothing matched with --fail-on-empty, output and stop here
+        let fail_on_empty = args.fail_on_empty.filter(|_| search_results.is_empty());
+        if matches!(args.mode, Mode::SearchOnly) || fail_on_empty.is_some() {
+            let response = BrainResponse {
+                query: query.clone(),
+                search_terms: SearchTerm::flatten(&search_terms),
                 matched_files: search_results,
-                response: String::new(),
+                timings: profile(args, &timings),
+                warnings: brain.take_warnings(),
+                ..Default::default()
             };
-            println!("{}", serde_json::to_string_pretty(&response)?);
+            emit_json(args.emit_json.as_deref(), &response, args.json_compact)?;
+            if matches!(args.format, OutputFormat::Json) {
+                println!("{}", to_json(&response, args.json_compact)?);
+            }
+            if let Some(code) = fail_on_empty {
+                exit_with(code);
+            }
+            return Ok(());
+        }
+    
+        (search_terms, search_results)
+    };
+    
+    // Check that the best match is relevant enough to answer from. Files given
+    // with --context-files are always used, since they weren't scored, and the
+    // model's 0-1 ratings from --rerank aren't on the keyword score scale.
+    let low_confidence = args.context_files.is_empty() && !args.rerank && !brain.is_confident(&search_results);
+    if low_confidence && config.search.low_relevance == LowRelevance::Skip {
+        let message = format!(
+            "No confident match: no file reached a relevance of {}",
+            config.search.generate_min_relevance
+        );
+        if matches!(args.format, OutputFormat::Text) {
+            println!("\n{}", message);
+        }
+        let response = BrainResponse {
+            query: query.clone(),
+            search_terms: SearchTerm::flatten(&search_terms),
+            matched_files: search_results,
+            response: ResponseBody::Text(message),
+            low_confidence,
+            timings: profile(args, &timings),
+            warnings: brain.take_warnings(),
+            ..Default::default()
+        };
+        emit_json(args.emit_json.as_deref(), &response, args.json_compact)?;
+        if matches!(args.format, OutputFormat::Json) {
+            println!("{}", to_json(&response, args.json_compact)?);
         }
         return Ok(());
     }
     
+    // Warn if the configured context is larger than the model can handle, once generation is
+    // certain to happen. The limit is in characters and the window in tokens,
+    // so the limit is converted with an estimate.
+    if matches!(args.mode, Mode::GenerateResponse) {
+        let context_tokens = ollama::estimated_tokens(config.ollama.max_context_length);
+        if let Some(window) = brain.ollama()?.model_context_window().await {
+            if context_tokens > window {
+                brain.warn(format_args!(
+                    "max_context_length ({} characters, about {} tokens) exceeds the context window of model {} ({} tokens)",
+                    config.ollama.max_context_length, context_tokens, config.ollama.model, window
+                ));
+            }
+        }
+    }
+    
     // Get file paths from search results
     let file_paths: Vec<String> = search_results.iter()
         .map(|r| r.path.clone())
         .collect();
     
     // Retrieve file contents
-    if matches!(args.format, OutputFormat::Text) {
+    if args.progress() {
         println!("\nRetrieving file contents...");
     }
-    let contents = get_contents(&file_paths)?;
+    let keywords = SearchTerm::flatten_text(&search_terms);
+    let highlight = args.highlight.then_some(keywords.as_slice());
+    let start = Instant::now();
+    let contents = if args.outline {
+        brain.outline(&file_paths, args.outline_lead).await?
+    } else {
+        brain.contents(&file_paths, highlight).await?
+    };
+    timings.contents_ms = Some(start.elapsed().as_millis());
+    
+    // Add any extra context before the file contents, so it counts against the context length
+    let mut extra = args.append_context.clone();
+    if let Some(path) = &args.context_file {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read context file: {}", path.display()))?;
+        extra.push(text);
+    }
+    let contents = prepend_context(&extra, &contents);
+    let used_files = brain.used_files(&file_paths, &contents);
+    for result in &mut search_results {
+        result.used = Some(used_files.contains(&result.path));
+    }
+    
+    let (context_chars, context_truncated_chars) = brain.context_chars(&contents);
+    if args.progress() {
+        eprintln!(
+            "Context: {} characters passed to the model, {} truncated",
+            context_chars, context_truncated_chars
+        );
+    }
+    if used_files.len() < file_paths.len() && args.progress() {
+        println!(
+            "Only {} of {} files fit into the context (max_context_length: {})",
+            used_files.len(),
+            file_paths.len(),
+            config.ollama.max_context_length
+        );
+    }
     
     // Generate response using Ollama
-    if matches!(args.format, OutputFormat::Text) {
+    if args.progress() {
         println!("\nGenerating response...");
     }
-    let response = ollama_client.generate_response(&args.query, &contents).await?;
+    let start = Instant::now();
+    let mut evidence = None;
+    let answer = if args.structured {
+        let answer = brain.answer_structured(&query, &contents).await?;
+        if answer.structured.is_none() {
+            brain.warn("The model did not return a valid structured answer, falling back to raw text");
+        }
+        answer
+    } else if args.evidence {
+        let (answer, quotes) = brain.answer_with_evidence(&query, &contents, &file_paths).await?;
+        if quotes.is_empty() {
+            brain.warn("The model did not quote any evidence");
+        }
+        evidence = Some(quotes);
+        answer
+    } else {
+        brain.answer(&query, &contents).await?
+    };
+    timings.generate_ms = Some(start.elapsed().as_millis());
+    let answer_text = answer
+        .structured
+        .as_ref()
+        .and_then(|value| value["answer"].as_str())
+        .unwrap_or(&answer.response)
+        .to_string();
+    
+    // Rate how well the response is grounded in the context, if requested
+    let grounding_score = if args.grounding {
+        let start = Instant::now();
+        let score = brain.grounding(&query, &contents, &answer_text).await;
+        timings.grounding_ms = Some(start.elapsed().as_millis());
+        match score {
+            Ok(score) => Some(score),
+            Err(e) => {
+                brain.warn(format_args!("Could not rate grounding: {}", e));
+                None
+            }
+        }
+    } else {
+        None
+    };
     
     // Output the final result
     if matches!(args.format, OutputFormat::Text) {
-        println!("\nResponse:");
-        println!("{}", response);
-    } else {
-        // JSON output
-        let brain_response = BrainResponse {
-            query: args.query.clone(),
-            search_terms,
-            matched_files: search_results,
-            response,
-        };
-        println!("{}", serde_json::to_string_pretty(&brain_response)?);
+        if args.progress() {
+            println!("\nResponse:");
+        }
+        if low_confidence && !args.quiet {
+            println!(
+                "(No file reached a relevance of {}, so this response may be based on marginally relevant notes.)",
+                config.search.generate_min_relevance
+            );
+        }
+        println!("{}", answer_text);
+        if let Some(key_points) = answer.structured.as_ref().and_then(|value| value["key_points"].as_array()) {
+            println!("\nKey points:");
+            for point in key_points.iter().filter_map(|point| point.as_str()) {
+                println!("- {}", point);
+            }
+        }
+        if let Some(quotes) = evidence.as_ref().filter(|quotes| !quotes.is_empty()) {
+            println!("\nEvidence:");
+            for quote in quotes {
+                let source = quote.source.as_deref().unwrap_or("unknown source");
+                let flag = if quote.found { "" } else { " [not found in the notes, possibly made up]" };
+                println!("- \"{}\" ({}){}", quote.quote, source, flag);
+            }
+        }
+        if let Some(score) = grounding_score {
+            println!("\nGrounding score: {:.2}", score);
+            if score < LOW_GROUNDING_SCORE && !args.quiet {
+                println!("The knowledge base may lack relevant material, so the response may be speculative.");
+            }
+        }
+    }
+    
+    let brain_response = BrainResponse {
+        query: query.clone(),
+        search_terms: SearchTerm::flatten(&search_terms),
+        matched_files: search_results,
+        response: match answer.structured {
+            Some(value) => ResponseBody::Structured(value),
+            None => ResponseBody::Text(answer.response),
+        },
+        raw_response: answer.raw_response,
+        used_files: Some(used_files),
+        grounding_score,
+        evidence,
+        context_chars: Some(context_chars),
+        context_truncated_chars: Some(context_truncated_chars),
+        low_confidence,
+        timings: profile(args, &timings),
+        warnings: brain.take_warnings(),
+    };
+    emit_json(args.emit_json.as_deref(), &brain_response, args.json_compact)?;
+    if matches!(args.format, OutputFormat::Json) {
+        println!("{}", to_json(&brain_response, args.json_compact)?);
     }
     
     Ok(())
@@ -183,16 +1091,27 @@ async fn run() -> Result<()> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    if let Err(e) = run().await {
-        eprintln!("Error: {}", e);
-        
-        // Print cause chain for better error diagnostics
-        let mut cause = e.source();
-        while let Some(e) = cause {
-            eprintln!("Caused by: {}", e);
-            cause = e.source();
+    // Parse CLI arguments
+    let args = Args::parse();
+    
+    // On Ctrl-C, exit right away, which abandons any in-flight Ollama request
+    let result = tokio::select! {
+        result = async {
+            // Printing the schema or version needs no knowledge base to watch
+            if args.watch && !matches!(args.command, Some(Command::Schema | Command::Version { .. })) {
+                watch(args).await
+            } else {
+                run_with_timeout(args).await
+            }
+        } => result,
+        _ = tokio::signal::ctrl_c() => {
+            eprintln!("Interrupted");
+            exit_with(INTERRUPTED_EXIT_CODE);
         }
-        
+    };
+    
+    if let Err(e) = result {
+        report_error(&e);
         std::process::exit(1);
     }
     
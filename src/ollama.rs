@@ -1,23 +1,96 @@
 use anyhow::{Context, Result};
 use ollama_rs::generation::completion::request::GenerationRequest;
+use ollama_rs::generation::completion::GenerationResponse;
+use ollama_rs::generation::options::GenerationOptions;
+use ollama_rs::generation::parameters::FormatType;
 use ollama_rs::Ollama;
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OnceCell, Semaphore};
 use url::Url;
 
+use crate::config::TruncationStrategy;
+use crate::search::SearchTerm;
+
+/// Rough number of characters per token, to compare character limits with context windows in tokens
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// How long the model information is waited for before the context window check is skipped
+const SHOW_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Estimates the number of tokens a text of `chars` characters takes up
+pub fn estimated_tokens(chars: usize) -> usize {
+    chars.div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Removes every region enclosed by the open and close delimiters, such as `<think>...</think>`
+pub fn strip_delimited(text: &str, open: &str, close: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(open) {
+        match rest[start + open.len()..].find(close) {
+            Some(end) => {
+                result.push_str(&rest[..start]);
+                rest = &rest[start + open.len() + end + close.len()..];
+            }
+            // Leave an unterminated region alone rather than dropping the rest of the text
+            None => break,
+        }
+    }
+    result.push_str(rest);
+
+    result.trim().to_string()
+}
+
 pub struct OllamaClient {
     client: Ollama,
+    http: reqwest::Client,
     model: String,
     max_context_length: usize,
+    truncation_strategy: TruncationStrategy,
+    term_separators: Vec<String>,
+    permits: Arc<Semaphore>,
+    stop: Vec<String>,
+    answer_language: Option<String>,
+    /// The model's context window, queried once and kept for the life of the client
+    context_window: OnceCell<usize>,
 }
 
 impl OllamaClient {
-    /// Truncates a string to a maximum number of characters, preserving Unicode character boundaries
-    fn truncate_to_char_limit(text: &str, max_chars: usize) -> String {
-        let char_count = text.chars().count();
-        if char_count > max_chars {
-            text.chars().take(max_chars).collect::<String>()
-        } else {
-            text.to_string()
+    /// Returns the ranges of character indices kept when truncating text to a maximum number of characters
+    /// The second range is empty unless both ends of the text are kept
+    pub fn kept_ranges(char_count: usize, max_chars: usize, strategy: TruncationStrategy) -> [Range<usize>; 2] {
+        if char_count <= max_chars {
+            return [0..char_count, 0..0];
         }
+
+        match strategy {
+            TruncationStrategy::Head => [0..max_chars, 0..0],
+            TruncationStrategy::Tail => [char_count - max_chars..char_count, 0..0],
+            TruncationStrategy::MiddleOut => {
+                let head = max_chars.div_ceil(2);
+                let tail = max_chars - head;
+                [0..head, char_count - tail..char_count]
+            }
+        }
+    }
+
+    /// Truncates a string to a maximum number of characters, preserving Unicode character boundaries
+    /// The strategy decides whether the beginning, the end, or both ends of the text are kept
+    fn truncate_to_char_limit(text: &str, max_chars: usize, strategy: TruncationStrategy) -> String {
+        let ranges = Self::kept_ranges(text.chars().count(), max_chars, strategy);
+        text.chars()
+            .enumerate()
+            .filter(|(i, _)| ranges.iter().any(|r| r.contains(i)))
+            .map(|(_, c)| c)
+            .collect::<String>()
     }
 
     pub fn new(endpoint: &str, model: &str, max_context_length: usize) -> Result<Self> {
@@ -28,8 +101,12 @@ impl OllamaClient {
                 endpoint.to_string()
             };
 
-        let url = Url::parse(&endpoint_with_protocol)
+        let mut url = Url::parse(&endpoint_with_protocol)
             .with_context(|| format!("Invalid endpoint URL: {}", endpoint))?;
+        // Request URLs are the endpoint followed by `api/...`, so a path prefix needs its trailing slash
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
 
         let url_str = url.to_string();
         let client = Ollama::try_new(url.clone())
@@ -37,78 +114,520 @@ impl OllamaClient {
 
         Ok(Self {
             client,
+            http: reqwest::Client::new(),
             model: model.to_string(),
             max_context_length,
+            truncation_strategy: TruncationStrategy::default(),
+            term_separators: Vec::new(),
+            permits: Arc::new(Semaphore::new(1)),
+            stop: Vec::new(),
+            answer_language: None,
+            context_window: OnceCell::new(),
         })
     }
 
-    /// Extracts search terms from a user query using Ollama
-    /// This includes both direct terms from the query and related/recalled terms
-    pub async fn extract_search_terms(&self, query: &str) -> Result<Vec<String>> {
-        let system = "You are a search term extraction assistant. Your task is to analyze queries and extract useful search terms. You can detect the language of queries. For non-English queries, you provide terms in both the original language and English translations. For English queries, you provide terms in English only.";
+    /// Sets how many generate requests may be in flight at once
+    pub fn with_max_concurrency(mut self, max_concurrency: NonZeroUsize) -> Self {
+        self.permits = Arc::new(Semaphore::new(max_concurrency.get()));
+        self
+    }
+
+    /// Sets the stop sequences sent with answer requests, ending generation where one appears
+    pub fn with_stop(mut self, stop: &[String]) -> Self {
+        self.stop = stop.to_vec();
+        self
+    }
+
+    /// Sets the language answers are written in, instead of the language of the query
+    pub fn with_answer_language(mut self, language: Option<&str>) -> Self {
+        self.answer_language = language.map(str::to_string);
+        self
+    }
+
+    /// Sends a generate request, waiting until fewer than the maximum number are in flight
+    async fn generate(&self, request: GenerationRequest<'_>) -> Result<GenerationResponse> {
+        let _permit = self.permits.acquire().await?;
+        Ok(self.client.generate(request).await?)
+    }
+
+    /// Sets the separators, in addition to newlines, that split a plain-text term list
+    pub fn with_term_separators(mut self, separators: &[String]) -> Self {
+        self.term_separators = separators.to_vec();
+        self
+    }
+
+    /// Sets which part of the context is kept when it exceeds `max_context_length`
+    pub fn with_truncation_strategy(mut self, strategy: TruncationStrategy) -> Self {
+        self.truncation_strategy = strategy;
+        self
+    }
+
+    /// Attaches a bearer token and additional headers to every request sent to Ollama
+    pub fn with_headers(mut self, auth_token: Option<&str>, headers: &HashMap<String, String>) -> Result<Self> {
+        if auth_token.is_none() && headers.is_empty() {
+            return Ok(self);
+        }
+
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name: {}", name))?;
+            let value = HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value for header: {}", name))?;
+            header_map.insert(name, value);
+        }
+        if let Some(token) = auth_token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .context("Invalid auth token")?;
+            value.set_sensitive(true);
+            header_map.insert(AUTHORIZATION, value);
+        }
+
+        self.http = reqwest::Client::builder()
+            .default_headers(header_map)
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        let url = self.client.url().clone();
+        let port = url.port_or_known_default().unwrap_or(80);
+        self.client = Ollama::new_with_client(url, port, self.http.clone());
+
+        Ok(self)
+    }
+
+    /// Parses the model's term list, falling back to one term per line if it is not JSON
+    /// Each fallback line is further split on any of the separators
+    fn parse_search_terms(text: &str, separators: &[String]) -> Vec<SearchTerm> {
+        let json = match (text.find('['), text.rfind(']')) {
+            (Some(start), Some(end)) if start < end => &text[start..=end],
+            _ => "",
+        };
+
+        if let Ok(terms) = serde_json::from_str::<Vec<SearchTerm>>(json) {
+            return terms
+                .into_iter()
+                .map(|t| SearchTerm { term: t.term.trim().to_string(), ..t })
+                .filter(|t| !t.term.is_empty())
+                .collect();
+        }
+
+        let mut parts: Vec<&str> = text.lines().collect();
+        for separator in separators.iter().filter(|s| !s.is_empty()) {
+            parts = parts.into_iter().flat_map(|part| part.split(separator.as_str())).collect();
+        }
+
+        parts
+            .into_iter()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .map(SearchTerm::direct)
+            .collect()
+    }
+
+    /// Reads the context window from an `/api/show` response
+    fn context_window_from_show(show: &Value) -> Option<usize> {
+        // A num_ctx parameter in the Modelfile takes precedence over the model's default
+        let num_ctx = show
+            .get("parameters")
+            .and_then(Value::as_str)
+            .and_then(|params| {
+                params.lines().find_map(|line| {
+                    let mut parts = line.split_whitespace();
+                    match (parts.next(), parts.next()) {
+                        (Some("num_ctx"), Some(value)) => value.parse().ok(),
+                        _ => None,
+                    }
+                })
+            });
+        if num_ctx.is_some() {
+            return num_ctx;
+        }
+
+        show.get("model_info")
+            .and_then(Value::as_object)?
+            .iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64())
+            .map(|value| value as usize)
+    }
+
+    /// Returns the model's context window in tokens, queried via Ollama's `/api/show` on first use
+    /// Returns None if the model information is unavailable, in which case the next call asks again
+    pub async fn model_context_window(&self) -> Option<usize> {
+        self.context_window.get_or_try_init(|| self.query_context_window()).await.ok().copied()
+    }
+
+    /// Queries the model's context window via Ollama's `/api/show`
+    async fn query_context_window(&self) -> Result<usize> {
+        let show: Value = self
+            .http
+            .post(self.api_url("show"))
+            .timeout(SHOW_TIMEOUT)
+            .json(&serde_json::json!({ "model": self.model }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Self::context_window_from_show(&show).context("Model information has no context window")
+    }
+
+    /// Returns the URL of an Ollama API endpoint, built the way ollama-rs builds its request URLs
+    fn api_url(&self, name: &str) -> String {
+        format!("{}api/{}", self.client.url_str(), name)
+    }
+
+    /// Returns the system prompt and the prompt sent to extract search terms from a query
+    pub fn extract_prompt(query: &str) -> (String, String) {
+        let system = "You are a search term extraction assistant. Your task is to analyze queries and extract useful search terms. You can detect the language of queries. For non-English queries, you provide terms in both the original language and English translations. For English queries, you provide terms in English only.".to_string();
         let prompt = format!(
-            "Extract the most important search terms from this query. Include both direct terms and related/recalled terms that would be useful for searching a knowledge base. Return only the terms, one per line, with no additional text or explanation:\n\n{}",
+            "Extract the most important search terms from this query. Include both direct terms and related/recalled terms that would be useful for searching a knowledge base. Return only a JSON array of objects of the form {{\"term\": \"...\", \"origin\": \"direct\"}}, where origin is \"direct\" for terms taken from the query and \"recalled\" for related terms, with no additional text or explanation:\n\n{}",
             query
         );
+        (system, prompt)
+    }
+
+    /// Extracts search terms from a user query using Ollama
+    /// This includes both direct terms from the query and related/recalled terms
+    pub async fn extract_search_terms(&self, query: &str) -> Result<Vec<SearchTerm>> {
+        let (system, prompt) = Self::extract_prompt(query);
 
         let request = GenerationRequest::new(self.model.clone(), prompt)
             .system(system);
             
         let response = self
-            .client
             .generate(request)
             .await
             .context("Failed to extract search terms using Ollama")?;
 
-        let terms: Vec<String> = response
-            .response
-            .lines()
-            .map(|line| line.trim().to_string())
-            .filter(|line| !line.is_empty())
-            .collect();
-
-        Ok(terms)
+        Ok(Self::parse_search_terms(&response.response, &self.term_separators))
     }
 
-    /// Generates a response based on the query and context
-    pub async fn generate_response(&self, query: &str, context: &str) -> Result<String> {
-        let truncated_context = Self::truncate_to_char_limit(context, self.max_context_length);
+    /// Builds the request for an answer to the query, following an answer style instruction if given
+    fn answer_request(&self, query: &str, context: &str, instruction: Option<&str>) -> GenerationRequest<'static> {
+        let truncated_context = Self::truncate_to_char_limit(context, self.max_context_length, self.truncation_strategy);
 
-        let system = "You are a knowledge assistant that provides accurate information based on the given context. Only use the provided information to answer queries. Do not make up facts or use external knowledge. Your answer must be in the same language as the query.";
+        let mut system = "You are a knowledge assistant that provides accurate information based on the given context. Only use the provided information to answer queries. Do not make up facts or use external knowledge.".to_string();
+        match &self.answer_language {
+            Some(language) => system.push_str(&format!(" Your answer must be in {}, regardless of the language of the query.", language)),
+            None => system.push_str(" Your answer must be in the same language as the query."),
+        }
+        if let Some(instruction) = instruction {
+            system.push(' ');
+            system.push_str(instruction);
+        }
         
         let prompt = format!(
             "Use the following information to answer the query:\n\nINFORMATION:\n{}\n\nQUERY:\n{}\n\nANSWER:",
             truncated_context, query
         );
 
-        let request = GenerationRequest::new(self.model.clone(), prompt)
-            .system(system);
+        GenerationRequest::new(self.model.clone(), prompt).system(system)
+    }
+
+    /// Builds the request for a plain-text answer, with the configured stop sequences if any
+    fn response_request(&self, query: &str, context: &str, instruction: Option<&str>) -> GenerationRequest<'static> {
+        let request = self.answer_request(query, context, instruction);
+        if self.stop.is_empty() {
+            request
+        } else {
+            request.options(GenerationOptions::default().stop(self.stop.clone()))
+        }
+    }
+
+    /// Generates a response based on the query and context, following an answer style instruction if given
+    pub async fn generate_response(&self, query: &str, context: &str, instruction: Option<&str>) -> Result<String> {
+        let request = self.response_request(query, context, instruction);
             
         let response = self
-            .client
             .generate(request)
             .await
             .context("Failed to generate response using Ollama")?;
 
         Ok(response.response)
     }
+
+    /// Builds the request for a JSON answer, without stop sequences, which could cut the object short
+    fn structured_request(&self, query: &str, context: &str, instruction: Option<&str>) -> GenerationRequest<'static> {
+        let mut request = self.answer_request(query, context, instruction).format(FormatType::Json);
+        if let Some(system) = request.system.as_mut() {
+            system.to_mut().push_str(" Respond only with a JSON object of the form {\"answer\": \"...\", \"key_points\": [\"...\"]}, where answer is the full answer and key_points lists its main points as short strings.");
+        }
+        request
+    }
+
+    /// Generates a response in JSON mode, asking for an object with `answer` and `key_points`
+    pub async fn generate_structured_response(&self, query: &str, context: &str, instruction: Option<&str>) -> Result<String> {
+        let request = self.structured_request(query, context, instruction);
+
+        let response = self
+            .generate(request)
+            .await
+            .context("Failed to generate structured response using Ollama")?;
+
+        Ok(response.response)
+    }
+
+    /// Parses a structured answer, returning None unless it is an object with
+    /// a string `answer` and a `key_points` array of strings
+    pub fn parse_structured_answer(text: &str) -> Option<serde_json::Value> {
+        let value: serde_json::Value = serde_json::from_str(text.trim()).ok()?;
+        value.get("answer")?.as_str()?;
+        let key_points = value.get("key_points")?.as_array()?;
+        if !key_points.iter().all(|point| point.is_string()) {
+            return None;
+        }
+        Some(value)
+    }
+
+    /// Returns the instruction to back each statement with a quote from a numbered source
+    pub fn evidence_instruction(sources: &[String]) -> String {
+        let sources: Vec<String> = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| format!("[{}] {}", i + 1, source))
+            .collect();
+        format!(
+            "After each key statement, quote the sentence of the information that supports it verbatim in double quotes, followed by the number of the file it comes from in square brackets, like \"quoted sentence\" [1]. The files are numbered as follows: {}.",
+            sources.join(", ")
+        )
+    }
+
+    /// Parses the quotes in a response that cite a source, as `"quote" [n]`, with the source's number
+    pub fn parse_quotes(response: &str) -> Vec<(String, usize)> {
+        let Ok(quote) = Regex::new(r#"["“]([^"”]+)["”]\s*\[(\d+)\]"#) else {
+            return Vec::new();
+        };
+        quote
+            .captures_iter(response)
+            .filter_map(|captures| Some((captures[1].trim().to_string(), captures[2].parse().ok()?)))
+            .collect()
+    }
+
+    /// Parses a score between 0 and 1 from the model's rating
+    /// A reply with more than one number, like "8/10", or a number out of range, like "80",
+    /// is on another scale and can't be read as a score, so it gives None rather than a guess.
+    fn parse_score(text: &str) -> Option<f64> {
+        let number = Regex::new(r"-?(?:\d+(?:\.\d+)?|\.\d+)").ok()?;
+        let mut numbers = number.find_iter(text);
+        let score: f64 = numbers.next()?.as_str().parse().ok()?;
+        (numbers.next().is_none() && (0.0..=1.0).contains(&score)).then_some(score)
+    }
+
+    /// Asks the model how much of an answer is supported by the context, from 0 to 1
+    pub async fn rate_grounding(&self, query: &str, context: &str, answer: &str) -> Result<f64> {
+        let truncated_context = Self::truncate_to_char_limit(context, self.max_context_length, self.truncation_strategy);
+
+        let system = "You are a fact-checking assistant. You judge how well answers are supported by the information they were based on.";
+
+        let prompt = format!(
+            "Rate how much of the answer is supported by the information, from 0 (not at all) to 1 (fully supported). Return only the number, with no additional text or explanation.\n\nINFORMATION:\n{}\n\nQUERY:\n{}\n\nANSWER:\n{}\n\nSCORE:",
+            truncated_context, query, answer
+        );
+
+        let request = GenerationRequest::new(self.model.clone(), prompt)
+            .system(system);
+
+        let response = self
+            .generate(request)
+            .await
+            .context("Failed to rate grounding using Ollama")?;
+
+        Self::parse_score(&response.response)
+            .ok_or_else(|| anyhow::anyhow!("Model returned no grounding score between 0 and 1: {}", response.response.trim()))
+    }
+
+    /// Asks the model how relevant an excerpt of a file is to the query, from 0 to 1
+    pub async fn rate_relevance(&self, query: &str, excerpt: &str) -> Result<f64> {
+        let system = "You are a search relevance assistant. You judge how useful documents are for answering queries.";
+
+        let prompt = format!(
+            "Rate how relevant the document is to the query, from 0 (irrelevant) to 1 (answers it directly). Return only the number, with no additional text or explanation.\n\nDOCUMENT:\n{}\n\nQUERY:\n{}\n\nSCORE:",
+            excerpt, query
+        );
+
+        let request = GenerationRequest::new(self.model.clone(), prompt)
+            .system(system);
+
+        let response = self
+            .generate(request)
+            .await
+            .context("Failed to rate relevance using Ollama")?;
+
+        Self::parse_score(&response.response)
+            .ok_or_else(|| anyhow::anyhow!("Model returned no relevance score between 0 and 1: {}", response.response.trim()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::search::TermOrigin;
 
     #[test]
     fn test_truncate_to_char_limit() {
         let unicode_text = "こんにちは世界！これはテストです。".repeat(100);
         let max_length = 10;
-        let truncated_context = OllamaClient::truncate_to_char_limit(&unicode_text, max_length);
+        let truncated_context = OllamaClient::truncate_to_char_limit(&unicode_text, max_length, TruncationStrategy::Head);
         
         assert_eq!(truncated_context.chars().count(), max_length);        
         assert!(unicode_text.starts_with(&truncated_context));
         assert!(std::str::from_utf8(truncated_context.as_bytes()).is_ok());
     }
 
+    #[test]
+    fn test_truncate_to_char_limit_tail() {
+        let unicode_text = "こんにちは世界！これはテストです。".repeat(100);
+        let max_length = 10;
+        let truncated_context = OllamaClient::truncate_to_char_limit(&unicode_text, max_length, TruncationStrategy::Tail);
+
+        assert_eq!(truncated_context.chars().count(), max_length);
+        assert!(unicode_text.ends_with(&truncated_context));
+    }
+
+    #[test]
+    fn test_truncate_to_char_limit_middle_out() {
+        let unicode_text = format!("始まり{}終わり", "中".repeat(100));
+        let max_length = 7;
+        let truncated_context = OllamaClient::truncate_to_char_limit(&unicode_text, max_length, TruncationStrategy::MiddleOut);
+
+        assert_eq!(truncated_context, "始まり中終わり");
+
+        let short_text = "短い";
+        assert_eq!(OllamaClient::truncate_to_char_limit(short_text, max_length, TruncationStrategy::MiddleOut), short_text);
+    }
+
+    #[test]
+    fn test_response_request_stop() {
+        let client = OllamaClient::new("http://localhost:11434", "model", 4096).unwrap();
+        let request = serde_json::to_value(client.response_request("q", "c", None)).unwrap();
+        assert!(request["options"].is_null());
+
+        let client = client.with_stop(&["\n\n".to_string(), "###".to_string()]);
+        let request = serde_json::to_value(client.response_request("q", "c", None)).unwrap();
+        assert_eq!(request["options"]["stop"], serde_json::json!(["\n\n", "###"]));
+    }
+
+    #[test]
+    fn test_structured_request_no_stop() {
+        let client = OllamaClient::new("http://localhost:11434", "model", 4096)
+            .unwrap()
+            .with_stop(&["\n\n".to_string()]);
+        let request = serde_json::to_value(client.structured_request("q", "c", None)).unwrap();
+        assert_eq!(request["format"], "json");
+        assert!(request["options"].is_null());
+    }
+
+    #[test]
+    fn test_answer_language() {
+        let client = OllamaClient::new("http://localhost:11434", "model", 4096).unwrap();
+        let request = serde_json::to_value(client.response_request("q", "c", None)).unwrap();
+        assert!(request["system"].as_str().unwrap().ends_with("in the same language as the query."));
+
+        let client = client.with_answer_language(Some("English"));
+        let request = serde_json::to_value(client.response_request("q", "c", None)).unwrap();
+        let system = request["system"].as_str().unwrap();
+        assert!(system.ends_with("in English, regardless of the language of the query."));
+        assert!(!system.contains("same language"));
+    }
+
+    #[test]
+    fn test_parse_quotes() {
+        let response = "Tokio uses work stealing \"The scheduler steals tasks\" [2]. Also “curly quotes” [1], but \"no source\".";
+        assert_eq!(
+            OllamaClient::parse_quotes(response),
+            vec![("The scheduler steals tasks".to_string(), 2), ("curly quotes".to_string(), 1)]
+        );
+
+        let instruction = OllamaClient::evidence_instruction(&["a.org".to_string(), "b.md".to_string()]);
+        assert!(instruction.ends_with("[1] a.org, [2] b.md."));
+    }
+
+    #[test]
+    fn test_parse_structured_answer() {
+        let value = OllamaClient::parse_structured_answer(r#"{"answer": "Yes", "key_points": ["a", "b"]}"#).unwrap();
+        assert_eq!(value["answer"], "Yes");
+        assert_eq!(value["key_points"][1], "b");
+
+        assert!(OllamaClient::parse_structured_answer("Yes, it is").is_none());
+        assert!(OllamaClient::parse_structured_answer(r#"{"answer": "Yes"}"#).is_none());
+        assert!(OllamaClient::parse_structured_answer(r#"{"answer": 1, "key_points": []}"#).is_none());
+        assert!(OllamaClient::parse_structured_answer(r#"{"answer": "Yes", "key_points": [1]}"#).is_none());
+    }
+
+    #[test]
+    fn test_parse_score() {
+        assert_eq!(OllamaClient::parse_score("0.75"), Some(0.75));
+        assert_eq!(OllamaClient::parse_score("Score: 1"), Some(1.0));
+        assert_eq!(OllamaClient::parse_score("no idea"), None);
+
+        // Scores on another scale aren't clamped into a high rating
+        assert_eq!(OllamaClient::parse_score("80"), None);
+        assert_eq!(OllamaClient::parse_score("8/10"), None);
+        assert_eq!(OllamaClient::parse_score("1 out of 5"), None);
+        assert_eq!(OllamaClient::parse_score("-0.5"), None);
+    }
+
+    #[test]
+    fn test_strip_delimited() {
+        let text = "<think>\nLet me think.\n</think>\n\nThe answer is 42.<think>more</think>";
+        assert_eq!(strip_delimited(text, "<think>", "</think>"), "The answer is 42.");
+
+        let unterminated = "Answer <think>unfinished";
+        assert_eq!(strip_delimited(unterminated, "<think>", "</think>"), unterminated);
+    }
+
+    #[test]
+    fn test_parse_search_terms_json() {
+        let text = "Here you go:\n[{\"term\": \"rust\", \"origin\": \"direct\"}, {\"term\": \"cargo\", \"origin\": \"recalled\"}]";
+        let terms = OllamaClient::parse_search_terms(text, &[]);
+
+        assert_eq!(terms.len(), 2);
+        assert_eq!(terms[0], SearchTerm::direct("rust"));
+        assert_eq!(terms[1], SearchTerm { origin: TermOrigin::Recalled, ..SearchTerm::direct("cargo") });
+    }
+
+    #[test]
+    fn test_parse_search_terms_falls_back_to_lines() {
+        let terms = OllamaClient::parse_search_terms("rust\n\n  cargo  \n", &[]);
+
+        assert_eq!(SearchTerm::flatten(&terms), vec!["rust", "cargo"]);
+        assert!(terms.iter().all(|t| t.origin == TermOrigin::Direct));
+    }
+
+    #[test]
+    fn test_parse_search_terms_separators() {
+        let separators = vec![",".to_string(), ";".to_string(), "/".to_string()];
+        let terms = OllamaClient::parse_search_terms("rust, cargo; crates\ntokio / async", &separators);
+
+        assert_eq!(SearchTerm::flatten(&terms), vec!["rust", "cargo", "crates", "tokio", "async"]);
+    }
+
+    #[test]
+    fn test_estimated_tokens() {
+        assert_eq!(estimated_tokens(0), 0);
+        assert_eq!(estimated_tokens(4096), 1024);
+        assert_eq!(estimated_tokens(4097), 1025);
+    }
+
+    #[test]
+    fn test_context_window_from_show() {
+        let show = serde_json::json!({
+            "parameters": "stop \"<|im_end|>\"",
+            "model_info": { "general.architecture": "llama", "llama.context_length": 8192 }
+        });
+        assert_eq!(OllamaClient::context_window_from_show(&show), Some(8192));
+
+        let show = serde_json::json!({
+            "parameters": "num_ctx 2048\nstop \"<|im_end|>\"",
+            "model_info": { "llama.context_length": 8192 }
+        });
+        assert_eq!(OllamaClient::context_window_from_show(&show), Some(2048));
+
+        assert_eq!(OllamaClient::context_window_from_show(&serde_json::json!({})), None);
+    }
+
     #[test]
     fn test_new_with_valid_url_with_protocol() {
         let result = OllamaClient::new("http://localhost:11434", "model", 4096);
@@ -145,6 +664,34 @@ mod tests {
         assert_eq!(client.max_context_length, 4096);
     }
 
+    #[test]
+    fn test_with_headers() {
+        let headers = HashMap::from([("X-Tenant".to_string(), "brain".to_string())]);
+        let client = OllamaClient::new("http://localhost:11434/api-proxy", "model", 4096)
+            .unwrap()
+            .with_headers(Some("secret"), &headers)
+            .unwrap();
+        assert_eq!(client.client.url().as_str(), "http://localhost:11434/api-proxy/");
+
+        let invalid = HashMap::from([("Bad Header".to_string(), "value".to_string())]);
+        let result = OllamaClient::new("localhost", "model", 4096)
+            .unwrap()
+            .with_headers(None, &invalid);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_api_url() {
+        let client = OllamaClient::new("localhost:11434", "model", 4096).unwrap();
+        assert_eq!(client.api_url("show"), "http://localhost:11434/api/show");
+
+        // A path prefix, such as that of an auth proxy, is kept with or without a trailing slash
+        for endpoint in ["https://host/ollama", "https://host/ollama/"] {
+            let client = OllamaClient::new(endpoint, "model", 4096).unwrap();
+            assert_eq!(client.api_url("show"), "https://host/ollama/api/show");
+        }
+    }
+
     #[test]
     fn test_new_with_invalid_url() {
         let result = OllamaClient::new("invalid:url:format", "model", 4096);
@@ -0,0 +1,114 @@
+/// Returns the level and text of a headline, without the leading stars, or None for other lines
+fn headline(line: &str) -> Option<(usize, &str)> {
+    let rest = line.trim_start_matches('*');
+    let level = line.len() - rest.len();
+    (level > 0 && rest.starts_with([' ', '\t'])).then(|| (level, rest.trim()))
+}
+
+/// Returns the headline lines of an org document, without the leading stars
+fn headlines(content: &str) -> impl Iterator<Item = &str> {
+    content.lines().filter_map(|line| headline(line).map(|(_, text)| text))
+}
+
+/// Splits a headline into its title and its trailing `:tag1:tag2:` block, if any
+fn split_headline(headline: &str) -> (&str, Option<&str>) {
+    if let Some((title, last)) = headline.rsplit_once(char::is_whitespace) {
+        if is_tag_block(last) {
+            return (title.trim_end(), Some(last));
+        }
+    } else if is_tag_block(headline) {
+        return ("", Some(headline));
+    }
+    (headline, None)
+}
+
+fn is_tag_block(text: &str) -> bool {
+    text.len() > 2 && text.starts_with(':') && text.ends_with(':') && !text.contains(char::is_whitespace)
+}
+
+/// Returns the level and title, without tags, of a heading line
+pub fn heading(line: &str) -> Option<(usize, &str)> {
+    headline(line).map(|(level, text)| (level, split_headline(text).0))
+}
+
+/// Returns the titles of all headings in an org document, without tags
+pub fn headings(content: &str) -> Vec<&str> {
+    headlines(content).map(|h| split_headline(h).0).collect()
+}
+
+/// Returns all tags of an org document, from both headlines and `#+FILETAGS`
+pub fn tags(content: &str) -> Vec<&str> {
+    let mut tags: Vec<&str> = Vec::new();
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        // Slice with get, since the 11th byte may fall inside a multi-byte character
+        if trimmed.get(..11).is_some_and(|prefix| prefix.eq_ignore_ascii_case("#+filetags:")) {
+            let rest = trimmed.get(11..).unwrap_or_default();
+            tags.extend(rest.split([':', ' ']).filter(|t| !t.is_empty()));
+        }
+    }
+
+    for headline in headlines(content) {
+        if let (_, Some(block)) = split_headline(headline) {
+            tags.extend(block.split(':').filter(|t| !t.is_empty()));
+        }
+    }
+
+    tags
+}
+
+/// Returns the key/value pairs of all `:PROPERTIES:` drawers in an org document
+pub fn properties(content: &str) -> Vec<(&str, &str)> {
+    let mut properties = Vec::new();
+    let mut in_drawer = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.eq_ignore_ascii_case(":properties:") {
+            in_drawer = true;
+        } else if trimmed.eq_ignore_ascii_case(":end:") {
+            in_drawer = false;
+        } else if in_drawer {
+            let property = trimmed
+                .strip_prefix(':')
+                .and_then(|rest| rest.split_once(':'))
+                .filter(|(key, _)| !key.is_empty() && !key.contains(char::is_whitespace));
+            if let Some((key, value)) = property {
+                properties.push((key, value.trim()));
+            }
+        }
+    }
+
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "#+TITLE: Notes\n#+FILETAGS: :notes:rust:\n\n* Ownership  :memory:\nBody text\n** Borrowing\n*bold* text\n";
+
+    #[test]
+    fn test_headings() {
+        assert_eq!(headings(DOC), vec!["Ownership", "Borrowing"]);
+    }
+
+    #[test]
+    fn test_tags() {
+        assert_eq!(tags(DOC), vec!["notes", "rust", "memory"]);
+    }
+
+    #[test]
+    fn test_tags_non_ascii() {
+        // Lines whose 11th byte is inside a multi-byte character must not panic
+        let doc = "日本語の文章です\n#+FILETAGS: :日本語:\n* Café au lait  :boisson:\n";
+        assert_eq!(tags(doc), vec!["日本語", "boisson"]);
+    }
+
+    #[test]
+    fn test_properties() {
+        let doc = "* Ownership\n:PROPERTIES:\n:AUTHOR: Jane Doe\n:STATUS:   draft\n:END:\n:NOT_A: property\n";
+        assert_eq!(properties(doc), vec![("AUTHOR", "Jane Doe"), ("STATUS", "draft")]);
+    }
+}
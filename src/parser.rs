@@ -0,0 +1,226 @@
+use std::path::Path;
+
+use crate::org;
+
+/// Structured fields of a document, used for field-scoped matching
+#[derive(Debug, Default, PartialEq)]
+pub struct Document<'a> {
+    /// Title of the document, if it declares one
+    pub title: Option<&'a str>,
+    /// Titles of the document's headings
+    pub headings: Vec<&'a str>,
+    /// Tags of the document
+    pub tags: Vec<&'a str>,
+    /// Key/value metadata of the document, such as org property drawer fields
+    pub properties: Vec<(&'a str, &'a str)>,
+    /// Text searched by unscoped terms
+    pub body: &'a str,
+}
+
+impl<'a> Document<'a> {
+    /// Adds the title, tags and properties of a sidecar metadata file
+    /// The document's own title, if it has one, takes precedence
+    pub fn merge_metadata(&mut self, metadata: Document<'a>) {
+        self.title = self.title.or(metadata.title);
+        self.tags.extend(metadata.tags);
+        self.properties.extend(metadata.properties);
+    }
+}
+
+/// Extracts structured fields from the content of a file type
+pub trait Parser: Sync {
+    fn parse<'a>(&self, content: &'a str) -> Document<'a>;
+
+    /// Returns the level and title of a heading line, or None for other lines
+    fn heading<'a>(&self, _line: &'a str) -> Option<(usize, &'a str)> {
+        None
+    }
+}
+
+/// Parser for org documents
+pub struct OrgParser;
+
+impl Parser for OrgParser {
+    fn parse<'a>(&self, content: &'a str) -> Document<'a> {
+        Document {
+            title: keyword_value(content, "#+title:"),
+            headings: org::headings(content),
+            tags: org::tags(content),
+            properties: org::properties(content),
+            body: content,
+        }
+    }
+
+    fn heading<'a>(&self, line: &'a str) -> Option<(usize, &'a str)> {
+        org::heading(line)
+    }
+}
+
+/// Parser for Markdown documents, with optional YAML frontmatter
+pub struct MarkdownParser;
+
+impl Parser for MarkdownParser {
+    fn parse<'a>(&self, content: &'a str) -> Document<'a> {
+        let (frontmatter, body) = split_frontmatter(content);
+
+        let headings: Vec<&str> = body
+            .lines()
+            .filter_map(|line| self.heading(line).map(|(_, title)| title))
+            .collect();
+
+        let title = frontmatter
+            .and_then(|fm| keyword_value(fm, "title:"))
+            .map(|title| title.trim_matches(['"', '\'']))
+            .or_else(|| body.lines().find_map(|line| line.strip_prefix("# ").map(str::trim)));
+
+        Document {
+            title,
+            headings,
+            tags: frontmatter.map(frontmatter_tags).unwrap_or_default(),
+            body,
+            ..Default::default()
+        }
+    }
+
+    fn heading<'a>(&self, line: &'a str) -> Option<(usize, &'a str)> {
+        let rest = line.trim_start_matches('#');
+        let level = line.len() - rest.len();
+        ((1..=6).contains(&level) && rest.starts_with([' ', '\t']))
+            .then(|| (level, rest.trim().trim_end_matches('#').trim_end()))
+    }
+}
+
+/// Parser for any other file type, searching the whole content as the body
+pub struct PlainParser;
+
+impl Parser for PlainParser {
+    fn parse<'a>(&self, content: &'a str) -> Document<'a> {
+        Document {
+            body: content,
+            ..Default::default()
+        }
+    }
+}
+
+/// Parses a sidecar metadata file with YAML-style `title:` and `tags:` fields
+/// The fields may be wrapped in `---` lines like Markdown frontmatter
+pub fn parse_sidecar(content: &str) -> Document<'_> {
+    let fields = match split_frontmatter(content) {
+        (Some(frontmatter), _) => frontmatter,
+        (None, _) => content,
+    };
+    Document {
+        title: keyword_value(fields, "title:").map(|title| title.trim_matches(['"', '\''])),
+        tags: frontmatter_tags(fields),
+        ..Default::default()
+    }
+}
+
+/// Returns the parser for a file, by its extension
+pub fn parser_for(path: &Path) -> &'static dyn Parser {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "org" => &OrgParser,
+        "md" | "markdown" => &MarkdownParser,
+        _ => &PlainParser,
+    }
+}
+
+/// Returns the trimmed value of the first line starting with a case-insensitive keyword
+fn keyword_value<'a>(content: &'a str, keyword: &str) -> Option<&'a str> {
+    content.lines().find_map(|line| {
+        let trimmed = line.trim();
+        let prefix = trimmed.get(..keyword.len())?;
+        prefix
+            .eq_ignore_ascii_case(keyword)
+            .then(|| trimmed[keyword.len()..].trim())
+    })
+}
+
+/// Splits a `---`-delimited YAML frontmatter block off the start of a document
+fn split_frontmatter(content: &str) -> (Option<&str>, &str) {
+    let Some(rest) = content.strip_prefix("---\n").or_else(|| content.strip_prefix("---\r\n")) else {
+        return (None, content);
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, content)
+}
+
+/// Returns the tags of a frontmatter block, as an inline `[a, b]` list or a `- item` list
+fn frontmatter_tags(frontmatter: &str) -> Vec<&str> {
+    let mut lines = frontmatter.lines();
+    while let Some(line) = lines.next() {
+        let Some(value) = line.strip_prefix("tags:") else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return lines
+                .map_while(|l| l.trim_start().strip_prefix("- "))
+                .map(|tag| tag.trim().trim_matches(['"', '\'']))
+                .collect();
+        }
+        return value
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(|tag| tag.trim().trim_matches(['"', '\'']))
+            .filter(|tag| !tag.is_empty())
+            .collect();
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_parser() {
+        let doc = "---\ntitle: \"Notes\"\ntags: [rust, memory]\n---\n# Ownership\nBody\n## Borrowing ##\n#hashtag\n";
+        let parsed = parser_for(Path::new("notes.md")).parse(doc);
+
+        assert_eq!(parsed.title, Some("Notes"));
+        assert_eq!(parsed.headings, vec!["Ownership", "Borrowing"]);
+        assert_eq!(parsed.tags, vec!["rust", "memory"]);
+        assert!(parsed.body.starts_with("# Ownership"));
+
+        let list_tags = "---\ntags:\n  - rust\n  - memory\n---\nBody\n";
+        assert_eq!(MarkdownParser.parse(list_tags).tags, vec!["rust", "memory"]);
+    }
+
+    #[test]
+    fn test_parse_sidecar() {
+        let sidecar = parse_sidecar("title: Shopping list\ntags: [errands, home]\n");
+        assert_eq!(sidecar.title, Some("Shopping list"));
+        assert_eq!(sidecar.tags, vec!["errands", "home"]);
+
+        let mut document = parser_for(Path::new("notes.org")).parse("* Milk  :dairy:\n");
+        document.merge_metadata(parse_sidecar("---\ntitle: Groceries\ntags:\n  - errands\n---\n"));
+        assert_eq!(document.title, Some("Groceries"));
+        assert_eq!(document.tags, vec!["dairy", "errands"]);
+    }
+
+    #[test]
+    fn test_parser_for() {
+        let doc = "#+TITLE: Notes\n* Ownership  :rust:\n";
+
+        let org = parser_for(Path::new("notes.ORG")).parse(doc);
+        assert_eq!(org.title, Some("Notes"));
+        assert_eq!(org.headings, vec!["Ownership"]);
+        assert_eq!(org.tags, vec!["rust"]);
+
+        let plain = parser_for(Path::new("notes.txt")).parse(doc);
+        assert_eq!(plain, Document { body: doc, ..Default::default() });
+    }
+}
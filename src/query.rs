@@ -0,0 +1,112 @@
+use crate::search::{Field, SearchTerm};
+
+/// A query split into field-scoped clauses and the remaining free text
+#[derive(Debug)]
+pub struct ParsedQuery {
+    /// Terms scoped to a field with a `field:` prefix
+    pub clauses: Vec<SearchTerm>,
+    /// Everything else, to be searched in file bodies
+    pub text: String,
+}
+
+/// Splits a query into whitespace-separated words, keeping double-quoted phrases together
+/// Returns each word without its quotes, with whether any part of it was quoted
+fn split_words(query: &str) -> Vec<(String, bool)> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                quoted = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                // Phrases of only whitespace, like `" "`, would match everywhere
+                if !word.trim().is_empty() {
+                    words.push((std::mem::take(&mut word), quoted));
+                }
+                quoted = false;
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.trim().is_empty() {
+        words.push((word, quoted));
+    }
+    words
+}
+
+/// Parses a query such as `tag:rust heading:ownership prop:STATUS=draft "borrow checker" lifetime`
+/// Words with an unrecognized prefix are kept as literal body text. Quoted phrases are
+/// searched as a whole, with any whitespace between their words.
+pub fn parse_query(query: &str) -> ParsedQuery {
+    let mut clauses = Vec::new();
+    let mut text = Vec::new();
+
+    for (word, quoted) in split_words(query) {
+        let word = word.as_str();
+        let field = word.split_once(':').and_then(|(prefix, value)| {
+            let field = match prefix.to_lowercase().as_str() {
+                "tag" => Field::Tag,
+                "heading" => Field::Heading,
+                "prop" if value.split_once('=').is_some_and(|(key, _)| !key.is_empty()) => Field::Property,
+                _ => return None,
+            };
+            (!value.trim().is_empty()).then_some((field, value))
+        });
+
+        match field {
+            Some((field, value)) => clauses.push(SearchTerm::scoped(value, field)),
+            None if quoted => clauses.push(SearchTerm::direct(word)),
+            None => text.push(word.to_string()),
+        }
+    }
+
+    ParsedQuery {
+        clauses,
+        text: text.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        let parsed = parse_query("tag:rust heading:ownership prop:STATUS=draft lifetime http://example.com tag: prop:=x");
+
+        assert_eq!(
+            parsed.clauses,
+            vec![
+                SearchTerm::scoped("rust", Field::Tag),
+                SearchTerm::scoped("ownership", Field::Heading),
+                SearchTerm::scoped("STATUS=draft", Field::Property),
+            ]
+        );
+        assert_eq!(parsed.text, "lifetime http://example.com tag: prop:=x");
+    }
+
+    #[test]
+    fn test_parse_query_phrases() {
+        let parsed = parse_query(r#"what about "distributed  systems" heading:"Project X" "unterminated phrase"#);
+
+        assert_eq!(
+            parsed.clauses,
+            vec![
+                SearchTerm::direct("distributed  systems"),
+                SearchTerm::scoped("Project X", Field::Heading),
+                SearchTerm::direct("unterminated phrase"),
+            ]
+        );
+        assert_eq!(parsed.text, "what about");
+
+        // Blank phrases are dropped rather than searched
+        let parsed = parse_query("rust \" \" \"\t\"");
+        assert!(parsed.clauses.is_empty());
+        assert_eq!(parsed.text, "rust");
+    }
+}
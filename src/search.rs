@@ -1,59 +1,564 @@
 use anyhow::Result;
 use rayon::prelude::*;
 use regex::Regex;
-use serde::Serialize;
-use std::fs;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
-use crate::config::Config;
+use crate::config::{Config, SortOrder};
+use crate::content::normalize_text;
+use crate::parser::{parse_sidecar, parser_for, Document};
+use crate::source::{FileSystemSource, KnowledgeSource};
+use crate::Warnings;
 
 // Search result structure
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct SearchResult {
     pub path: String,
     pub relevance: f64,
+    /// Position in the results, starting at 1
+    pub rank: usize,
+    /// Whether the file fit into the context passed to the model, present when a response was generated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used: Option<bool>,
+    /// Lowercased file extension, absent for files without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension: Option<String>,
 }
 
-/// Searches files in the knowledge base for the given keywords
-pub fn search_files(config: &Config, keywords: &[String]) -> Result<Vec<SearchResult>> {
-    let root_path = Path::new(&config.knowledge.root_path);
-    if !root_path.exists() {
-        return Err(anyhow::anyhow!("Knowledge base path does not exist: {}", config.knowledge.root_path));
+/// Results of a search, with the number of files it considered
+#[derive(Debug)]
+pub struct SearchOutcome {
+    pub results: Vec<SearchResult>,
+    /// Files listed as candidates before scoring; 0 means the knowledge base has nothing to search
+    pub candidates: usize,
+}
+
+impl SearchResult {
+    /// Short type label such as `[org]` for text output, empty for files without an extension
+    pub fn label(&self) -> String {
+        file_label(self.extension.as_deref())
     }
+}
 
-    // Create regex patterns for each keyword
-    let patterns: Vec<Regex> = keywords
-        .iter()
-        .map(|k| Regex::new(&format!(r"(?i){}", regex::escape(k))))
-        .collect::<std::result::Result<Vec<_>, _>>()?;
+/// Returns the lowercased extension of a path, if it has one
+pub fn file_extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+}
 
-    // Collect all .org files
-    let files: Vec<PathBuf> = WalkDir::new(root_path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            e.path().extension().map_or(false, |ext| ext == "org")
+/// Formats an extension as a type label followed by a space, e.g. `[md] `
+pub fn file_label(extension: Option<&str>) -> String {
+    extension.map(|ext| format!("[{}] ", ext)).unwrap_or_default()
+}
+
+/// Where a search term came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TermOrigin {
+    /// Taken directly from the query
+    #[default]
+    Direct,
+    /// Related term recalled or expanded by the model
+    Recalled,
+}
+
+/// Part of a file a search term is matched against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    /// The file content, without metadata such as Markdown frontmatter
+    #[default]
+    Body,
+    /// Tags, such as org headline tags or Markdown frontmatter tags
+    Tag,
+    /// Heading titles and the document title
+    Heading,
+    /// Org property drawer fields, matched as `KEY=value`
+    Property,
+}
+
+/// A search term together with its origin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchTerm {
+    pub term: String,
+    #[serde(default)]
+    pub origin: TermOrigin,
+    #[serde(default)]
+    pub field: Field,
+}
+
+impl SearchTerm {
+    /// Creates a term taken directly from the query
+    pub fn direct(term: &str) -> Self {
+        Self::scoped(term, Field::Body)
+    }
+
+    /// Creates a term taken directly from the query, matched only against the given field
+    pub fn scoped(term: &str, field: Field) -> Self {
+        Self {
+            term: term.to_string(),
+            origin: TermOrigin::Direct,
+            field,
+        }
+    }
+
+    /// Flattens search terms into plain strings
+    pub fn flatten(terms: &[SearchTerm]) -> Vec<String> {
+        terms.iter().map(|t| t.term.clone()).collect()
+    }
+
+    /// Flattens only the terms matched against text (body and heading terms), e.g. for highlighting
+    pub fn flatten_text(terms: &[SearchTerm]) -> Vec<String> {
+        terms
+            .iter()
+            .filter(|t| matches!(t.field, Field::Body | Field::Heading))
+            .map(|t| t.term.clone())
+            .collect()
+    }
+}
+
+/// Builds the case-insensitive regex used to match a keyword
+/// The words of a multi-word keyword may be separated by any whitespace, including line breaks.
+/// Fails for a blank keyword, whose empty pattern would match every file.
+pub fn keyword_pattern(keyword: &str) -> Result<Regex> {
+    let words: Vec<String> = keyword.split_whitespace().map(regex::escape).collect();
+    if words.is_empty() {
+        return Err(anyhow::anyhow!("Search term is empty: {:?}", keyword));
+    }
+    Ok(Regex::new(&format!(r"(?i){}", words.join(r"\s+")))?)
+}
+
+/// Strips diacritics from text, so that e.g. "café" becomes "cafe"
+/// Only marks on Latin, Greek and Cyrillic letters are stripped; kana voicing marks, among
+/// others, tell distinct words apart and are kept.
+pub fn fold_diacritics(text: &str) -> String {
+    let mut base = None;
+    text.nfd()
+        .filter(|&c| {
+            if !is_combining_mark(c) {
+                base = Some(c);
+                return true;
+            }
+            !base.is_some_and(has_foldable_marks)
         })
-        .map(|e| e.path().to_path_buf())
+        .nfc()
+        .collect()
+}
+
+/// Whether diacritics on the letter are folded away: Latin, Greek and Cyrillic letters
+fn has_foldable_marks(c: char) -> bool {
+    matches!(c,
+        'A'..='Z' | 'a'..='z' | '\u{00C0}'..='\u{024F}' // Latin-1 Supplement, Latin Extended-A/B
+        | '\u{0370}'..='\u{03FF}' // Greek and Coptic
+        | '\u{0400}'..='\u{052F}' // Cyrillic and Cyrillic Supplement
+        | '\u{1E00}'..='\u{1FFF}' // Latin Extended Additional, Greek Extended
+    )
+}
+
+/// Scores the matches of a single keyword, linearly or with diminishing returns
+fn match_score(matches: usize, saturation: bool) -> f64 {
+    if saturation {
+        (matches as f64).ln_1p()
+    } else {
+        matches as f64
+    }
+}
+
+/// Returns the largest number of distinct keywords matching within `window` bytes of each other
+/// `offsets` holds the start offset of each match together with the index of its keyword
+fn max_keywords_within(offsets: &mut [(usize, usize)], window: usize) -> usize {
+    offsets.sort_unstable();
+
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    let mut best = 0;
+    let mut start = 0;
+    for &(offset, keyword) in offsets.iter() {
+        *counts.entry(keyword).or_default() += 1;
+        while offset - offsets[start].0 > window {
+            let first = offsets[start].1;
+            if let Some(count) = counts.get_mut(&first) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&first);
+                }
+            }
+            start += 1;
+        }
+        best = best.max(counts.len());
+    }
+    best
+}
+
+/// Reads a file from the source as normalized text, failing for missing and non-UTF-8 files
+fn read_text(source: &dyn KnowledgeSource, path: &Path) -> Result<String> {
+    let bytes = source.read_file(path)?.ok_or_else(|| anyhow::anyhow!("File not found"))?;
+    Ok(normalize_text(String::from_utf8(bytes.to_vec())?))
+}
+
+/// Reads a file and its sidecar metadata, if configured and present, as search terms are matched against them
+/// Both are folded if diacritics are folded, leaving the files themselves untouched.
+fn read_searchable(config: &Config, source: &dyn KnowledgeSource, path: &Path) -> Result<(String, Option<String>)> {
+    let fold = config.search.fold_diacritics;
+    let content = read_text(source, path)?;
+    let sidecar = config.knowledge.sidecar_suffix.as_ref().and_then(|suffix| {
+        let mut sidecar_path = path.as_os_str().to_owned();
+        sidecar_path.push(suffix);
+        let bytes = source.read_file(Path::new(&sidecar_path)).ok().flatten()?;
+        normalize_text(String::from_utf8(bytes.to_vec()).ok()?).into()
+    });
+    if fold {
+        Ok((fold_diacritics(&content), sidecar.as_deref().map(fold_diacritics)))
+    } else {
+        Ok((content, sidecar))
+    }
+}
+
+/// Extracts the structured fields of a file with the parser for its type, merging in its sidecar metadata
+fn parse_searchable<'a>(path: &Path, content: &'a str, sidecar: Option<&'a str>) -> Document<'a> {
+    let mut document = parser_for(path).parse(content);
+    if let Some(sidecar) = sidecar {
+        document.merge_metadata(parse_sidecar(sidecar));
+    }
+    document
+}
+
+/// Returns the text heading terms are matched against: the document's title and headings
+/// A Markdown title taken from the first heading is only counted once.
+fn heading_text(document: &Document) -> String {
+    let title = document.title.filter(|title| !document.headings.contains(title));
+    title.iter().chain(&document.headings).copied().collect::<Vec<_>>().join("\n")
+}
+
+/// When a pass over the files stops early: once cancelled, or at the deadline
+struct Scan<'a> {
+    cancel: &'a AtomicBool,
+    deadline: Option<Instant>,
+    timed_out: &'a AtomicBool,
+}
+
+impl Scan<'_> {
+    /// Returns whether the pass should stop, recording whether it ran out of time
+    fn stopped(&self) -> bool {
+        if self.cancel.load(Ordering::Relaxed) || self.timed_out.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.timed_out.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+}
+
+/// Counts, for each pattern, the number of files it matches
+/// Each pattern is matched against the same part of a file as when scoring, e.g. the headings for `heading:` terms.
+fn document_frequencies(
+    config: &Config,
+    files: &[(PathBuf, f64)],
+    patterns: &[(Regex, f64, Field)],
+    source: &dyn KnowledgeSource,
+    scan: &Scan,
+) -> Vec<usize> {
+    let titles_only = config.search.titles_only;
+    files
+        .par_iter()
+        .filter(|_| !scan.stopped())
+        .filter_map(|(path, _)| {
+            let (content, sidecar) = read_searchable(config, source, path).ok()?;
+            let document = parse_searchable(path, &content, sidecar.as_deref());
+            let headings = heading_text(&document);
+            let counts = patterns
+                .iter()
+                .map(|(pattern, _, field)| {
+                    let text = match field {
+                        Field::Body if titles_only => &headings,
+                        Field::Heading => &headings,
+                        _ => document.body,
+                    };
+                    usize::from(pattern.is_match(text))
+                })
+                .collect::<Vec<_>>();
+            Some(counts)
+        })
+        .reduce(
+            || vec![0; patterns.len()],
+            |a: Vec<usize>, b: Vec<usize>| a.iter().zip(&b).map(|(x, y)| x + y).collect(),
+        )
+}
+
+/// Smoothed inverse document frequency: 1 for a term in every file, growing as the term gets rarer
+fn inverse_document_frequency(files: usize, df: usize) -> f64 {
+    ((files as f64 + 1.0) / (df as f64 + 1.0)).ln() + 1.0
+}
+
+/// Searches files in the knowledge base for the given search terms
+pub fn search_files(config: &Config, terms: &[SearchTerm]) -> Result<Vec<SearchResult>> {
+    search_files_limited(config, terms, config.knowledge.max_files)
+}
+
+/// Searches files in the knowledge base, returning up to `limit` results instead of `max_files`
+/// Warnings are printed to stderr only; use [`search_files_cancellable`] to collect them.
+pub fn search_files_limited(config: &Config, terms: &[SearchTerm], limit: usize) -> Result<Vec<SearchResult>> {
+    let source = FileSystemSource::default();
+    search_files_cancellable(config, terms, limit, &AtomicBool::new(false), &source, &Warnings::default())
+        .map(|outcome| outcome.results)
+}
+
+/// Drops results beyond the first `max` from each directory, keeping the order of the rest
+fn cap_per_directory(results: &mut Vec<(PathBuf, f64)>, max: NonZeroUsize) {
+    let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    results.retain(|(path, _)| {
+        let count = counts.entry(path.parent().unwrap_or(Path::new("")).to_path_buf()).or_default();
+        *count += 1;
+        *count <= max.get()
+    });
+}
+
+/// Returns the configured synonyms of unscoped and heading terms, each with the synonym weight
+/// Trigger terms are matched case-insensitively, and synonyms already among the terms are skipped.
+fn synonym_terms(config: &Config, terms: &[SearchTerm]) -> Vec<(SearchTerm, f64)> {
+    if config.synonyms.is_empty() {
+        return Vec::new();
+    }
+    let synonyms: HashMap<String, &Vec<String>> = config
+        .synonyms
+        .iter()
+        .map(|(trigger, expansions)| (trigger.to_lowercase(), expansions))
         .collect();
+    let mut seen: HashSet<(String, Field)> = terms.iter().map(|t| (t.term.to_lowercase(), t.field)).collect();
+
+    let mut expanded = Vec::new();
+    for term in terms.iter().filter(|t| matches!(t.field, Field::Body | Field::Heading)) {
+        let Some(expansions) = synonyms.get(&term.term.to_lowercase()) else {
+            continue;
+        };
+        for expansion in expansions.iter() {
+            if seen.insert((expansion.to_lowercase(), term.field)) {
+                let synonym = SearchTerm {
+                    term: expansion.clone(),
+                    ..term.clone()
+                };
+                expanded.push((synonym, config.search.synonym_weight));
+            }
+        }
+    }
+    expanded
+}
+
+/// Searches files in the knowledge base, stopping with an error once `cancel` is set
+/// Files are listed and read through `source`, whose cache a later stage can share.
+/// Problems that don't stop the search, like a timeout, are reported to `warnings`.
+pub fn search_files_cancellable(
+    config: &Config,
+    terms: &[SearchTerm],
+    limit: usize,
+    cancel: &AtomicBool,
+    source: &dyn KnowledgeSource,
+    warnings: &Warnings,
+) -> Result<SearchOutcome> {
+    // Create regex patterns for each keyword, weighted by the term's origin.
+    // Tags and properties must match exactly, so their pattern is anchored
+    // to a whole line of the newline-separated tag or property list.
+    // Configured synonyms are matched too, at a lower weight.
+    let fold = config.search.fold_diacritics;
+    let mut patterns: Vec<(Regex, f64, Field)> = terms
+        .iter()
+        .map(|t| (t.clone(), 1.0))
+        .chain(synonym_terms(config, terms))
+        .map(|(t, multiplier)| {
+            let weight = multiplier * match t.origin {
+                TermOrigin::Direct => 1.0,
+                TermOrigin::Recalled => config.search.recalled_weight,
+            };
+            let term = if fold { fold_diacritics(&t.term) } else { t.term.clone() };
+            let pattern = match t.field {
+                Field::Tag => Regex::new(&format!(r"(?im)^{}$", regex::escape(&term)))?,
+                Field::Property => {
+                    let (key, value) = term.split_once('=').unwrap_or((&term, ""));
+                    Regex::new(&format!(
+                        r"(?im)^{}={}$",
+                        regex::escape(key.trim()),
+                        regex::escape(value.trim())
+                    ))?
+                }
+                _ => keyword_pattern(&term)?,
+            };
+            Ok((pattern, weight, t.field))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let exclude_patterns = config
+        .search
+        .exclude_terms
+        .iter()
+        .map(|t| keyword_pattern(&if fold { fold_diacritics(t) } else { t.clone() }))
+        .collect::<Result<Vec<_>>>()?;
+    let titles_only = config.search.titles_only;
+    let heading_weight = if titles_only { 0.0 } else { config.search.heading_weight };
+    // File names are titles too, so a titles-only search matches them even if their weight is off
+    let filename_weight = match config.search.filename_weight {
+        0.0 if titles_only => config.search.body_weight,
+        weight => weight,
+    };
+    let needs_headings = titles_only
+        || heading_weight != 0.0
+        || patterns.iter().any(|(_, _, field)| *field == Field::Heading);
+    let needs_tags = patterns.iter().any(|(_, _, field)| *field == Field::Tag);
+    let property_clauses = patterns.iter().filter(|(_, _, field)| *field == Field::Property).count();
+
+    // Collect candidate files from every root up front, so that a single parallel
+    // pass scores them all regardless of how many roots there are
+    let files = source.list_files(config, warnings)?;
+
+    // Files not started by the deadline are skipped, keeping the matches found so far
+    let deadline = config
+        .search
+        .max_duration_ms
+        .map(|ms| Instant::now() + Duration::from_millis(ms));
+    let timed_out = AtomicBool::new(false);
+    let scan = Scan { cancel, deadline, timed_out: &timed_out };
+
+    // Weight keywords by their rarity, which takes a first pass to count the files mentioning each.
+    // The pass counts towards the deadline, so running out of time in it leaves nothing to score.
+    if config.search.idf_weighting {
+        let frequencies = document_frequencies(config, &files, &patterns, source, &scan);
+        for ((_, weight, field), df) in patterns.iter_mut().zip(frequencies) {
+            if matches!(field, Field::Body | Field::Heading) {
+                *weight *= inverse_document_frequency(files.len(), df);
+            }
+        }
+    }
+
+    // Number of results good enough to count towards an early exit. With a per-directory cap,
+    // only the results the cap keeps count, so that exiting early still leaves `limit` of them.
+    let good_enough = AtomicUsize::new(0);
+    let good_enough_per_dir: Mutex<HashMap<PathBuf, usize>> = Mutex::new(HashMap::new());
+    let early_exit = config.search.early_exit;
+    let proximity = config.search.proximity;
 
     // Search files in parallel
     let results: Vec<(PathBuf, f64)> = files
         .par_iter()
-        .filter_map(|file_path| {
-            match fs::read_to_string(file_path) {
-                Ok(content) => {
-                    // Calculate relevance score based on keyword matches
+        .filter_map(|(file_path, root_weight)| {
+            if scan.stopped() || (early_exit && good_enough.load(Ordering::Relaxed) >= limit) {
+                return None;
+            }
+
+            match read_searchable(config, source, file_path) {
+                Ok((content, sidecar)) => {
+                    let file_stem = file_path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy())
+                        .unwrap_or_default();
+                    let file_stem = if fold { fold_diacritics(&file_stem).into() } else { file_stem };
+
+                    // Extract the structured fields with the parser for the file type
+                    let document = parse_searchable(file_path, &content, sidecar.as_deref());
+
+                    // Files mentioning an excluded term are dropped, or penalized if configured
+                    let excluded = exclude_patterns.iter().any(|p| p.is_match(document.body));
+                    if excluded && config.search.exclude_weight <= 0.0 {
+                        return None;
+                    }
+                    let headings = if needs_headings { heading_text(&document) } else { String::new() };
+                    let tags = if needs_tags {
+                        document.tags.join("\n")
+                    } else {
+                        String::new()
+                    };
+                    let properties = if property_clauses > 0 {
+                        document
+                            .properties
+                            .iter()
+                            .map(|(key, value)| format!("{}={}", key, value))
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    } else {
+                        String::new()
+                    };
+
+                    // Calculate relevance score based on keyword matches, collecting
+                    // the offsets of body matches if their proximity is scored
                     let mut score = 0.0;
-                    for pattern in &patterns {
-                        let matches = pattern.find_iter(&content).count();
+                    let mut matched = false;
+                    let mut offsets: Vec<(usize, usize)> = Vec::new();
+                    let mut matched_properties = 0;
+                    for (keyword, (pattern, weight, field)) in patterns.iter().enumerate() {
+                        let text = match field {
+                            Field::Body if titles_only => &headings,
+                            Field::Body => document.body,
+                            Field::Heading => &headings,
+                            Field::Tag => &tags,
+                            Field::Property => &properties,
+                        };
+                        let matches = if proximity && *field == Field::Body {
+                            let before = offsets.len();
+                            offsets.extend(pattern.find_iter(text).map(|m| (m.start(), keyword)));
+                            offsets.len() - before
+                        } else {
+                            pattern.find_iter(text).count()
+                        };
+                        // The score is a weighted sum over the locations an unscoped term matches in
+                        let location_weight = if *field == Field::Body { config.search.body_weight } else { 1.0 };
                         if matches > 0 {
-                            score += matches as f64;
+                            matched = true;
+                            score += match_score(matches, config.search.saturation) * weight * location_weight;
+                            if *field == Field::Property {
+                                matched_properties += 1;
+                            }
+                        }
+
+                        // Body terms also count when they appear in a heading or the file name
+                        if *field == Field::Body {
+                            if heading_weight != 0.0 {
+                                let matches = pattern.find_iter(&headings).count();
+                                matched |= matches > 0;
+                                score += match_score(matches, config.search.saturation) * weight * heading_weight;
+                            }
+                            let matches = pattern.find_iter(&file_stem).count();
+                            matched |= matches > 0;
+                            score += match_score(matches, config.search.saturation) * weight * filename_weight;
                         }
                     }
+
+                    // Leave out files missing a required property
+                    if config.search.require_properties && matched_properties < property_clauses {
+                        return None;
+                    }
+
+                    // Reward each additional keyword matching close to the others
+                    if proximity {
+                        let close = max_keywords_within(&mut offsets, config.search.proximity_window);
+                        score += close.saturating_sub(1) as f64 * config.search.proximity_boost;
+                    }
+                    score *= root_weight;
+                    if excluded {
+                        score *= config.search.exclude_weight;
+                    }
                     
-                    if score > 0.0 {
+                    // A NaN score, e.g. from a NaN weight, is kept to be ranked last below,
+                    // but only if a term matched, as NaN weights turn zero scores into NaN too
+                    if score > 0.0 || (matched && score.is_nan()) {
+                        if score >= config.search.min_score {
+                            let kept = config.search.max_per_dir.is_none_or(|max| {
+                                let mut counts = good_enough_per_dir.lock().unwrap();
+                                let dir = file_path.parent().unwrap_or(Path::new("")).to_path_buf();
+                                let count = counts.entry(dir).or_default();
+                                *count += 1;
+                                *count <= max.get()
+                            });
+                            if kept {
+                                good_enough.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
                         Some((file_path.clone(), score))
                     } else {
                         None
@@ -64,32 +569,75 @@ pub fn search_files(config: &Config, keywords: &[String]) -> Result<Vec<SearchRe
         })
         .collect();
 
-    // Sort by relevance (descending) and limit to max_files
+    if cancel.load(Ordering::Relaxed) {
+        return Err(anyhow::anyhow!("Search cancelled"));
+    }
+    if timed_out.load(Ordering::Relaxed) {
+        warnings.warn(format_args!(
+            "Search stopped after search.max_duration_ms ({} ms), ranking the {} files matched so far",
+            config.search.max_duration_ms.unwrap_or_default(),
+            results.len()
+        ));
+    }
+
+    // Treat NaN scores as the lowest relevance, rather than letting them scramble the order
     let mut sorted_results = results;
-    sorted_results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-    sorted_results.truncate(config.knowledge.max_files);
+    let nan_scores = sorted_results.iter().filter(|(_, score)| score.is_nan()).count();
+    if nan_scores > 0 {
+        warnings.warn(format_args!(
+            "{} files got an invalid relevance score (NaN), ranking them last; check the search weights",
+            nan_scores
+        ));
+        for (_, score) in sorted_results.iter_mut().filter(|(_, score)| score.is_nan()) {
+            *score = 0.0;
+        }
+    }
+
+    // Sort in the configured order and limit to max_files.
+    // Ties are broken by path, so that results are the same on every run
+    // regardless of the order the parallel scan collected them in.
+    match config.search.sort {
+        SortOrder::Relevance => {
+            sorted_results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        }
+        SortOrder::Path => sorted_results.sort_by(|a, b| a.0.cmp(&b.0)),
+        SortOrder::Mtime => sorted_results.sort_by_cached_key(|(path, _)| {
+            let mtime = source.modified(path);
+            (std::cmp::Reverse(mtime), path.clone())
+        }),
+    }
+    if let Some(max_per_dir) = config.search.max_per_dir {
+        cap_per_directory(&mut sorted_results, max_per_dir);
+    }
+    sorted_results.truncate(limit);
 
     // Convert to SearchResult format
     let search_results = sorted_results
         .into_iter()
-        .map(|(path, relevance)| {
+        .enumerate()
+        .map(|(i, (path, relevance))| {
+            let path = path.to_string_lossy().to_string();
             SearchResult {
-                path: path.to_string_lossy().to_string(),
+                extension: file_extension(&path),
+                path,
                 relevance,
+                rank: i + 1,
+                used: None,
             }
         })
         .collect();
 
-    Ok(search_results)
+    Ok(SearchOutcome { results: search_results, candidates: files.len() })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::File;
+    use std::fs::{self, File};
     use std::io::Write as IoWrite;
+    use std::sync::Arc;
     use tempfile::tempdir;
-    use crate::config::{Config, create_test_config_for_tests};
+    use crate::config::{Config, RootConfig, create_test_config_for_tests};
 
     fn create_test_environment() -> (tempfile::TempDir, Config) {
         let temp_dir = tempdir().unwrap();
@@ -115,16 +663,18 @@ mod tests {
         let (temp_dir, config) = create_test_environment();
         
         // Test with keywords that should match
-        let keywords = vec!["test".to_string(), "keywords".to_string()];
+        let keywords = vec![SearchTerm::direct("test"), SearchTerm::direct("keywords")];
         let results = search_files(&config, &keywords).unwrap();
         
         // Should find our test file
         assert!(!results.is_empty());
         assert!(results[0].path.contains("test.org"));
         assert!(results[0].relevance > 0.0);
+        assert_eq!(results[0].extension.as_deref(), Some("org"));
+        assert_eq!(results[0].label(), "[org] ");
         
         // Test with keywords that shouldn't match
-        let keywords = vec!["nonexistent".to_string(), "notfound".to_string()];
+        let keywords = vec![SearchTerm::direct("nonexistent"), SearchTerm::direct("notfound")];
         let results = search_files(&config, &keywords).unwrap();
         
         // Should not find any files
@@ -133,4 +683,716 @@ mod tests {
         // Clean up
         drop(temp_dir);
     }
+
+    #[test]
+    fn test_search_files_idf_weighting() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(temp_dir.path().join("a.org"), "project project").unwrap();
+        fs::write(temp_dir.path().join("b.org"), "project mmap").unwrap();
+        fs::write(temp_dir.path().join("c.org"), "project").unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        let keywords = vec![SearchTerm::direct("project"), SearchTerm::direct("mmap")];
+
+        // Without IDF, a.org and b.org tie on two matches each
+        let results = search_files(&config, &keywords).unwrap();
+        assert!(results[0].path.ends_with("a.org"));
+        assert_eq!(results[0].relevance, results[1].relevance);
+
+        // With IDF, the rare "mmap" outweighs a second "project", which is in every file
+        config.search.idf_weighting = true;
+        let results = search_files(&config, &keywords).unwrap();
+        assert!(results[0].path.ends_with("b.org"));
+        assert!(results[0].relevance > results[1].relevance);
+        assert_eq!(results[2].relevance, 1.0);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_idf_heading() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(temp_dir.path().join("a.org"), "* Rust\nrust").unwrap();
+        fs::write(temp_dir.path().join("b.org"), "* Notes\nrust").unwrap();
+        fs::write(temp_dir.path().join("c.org"), "* Notes\nrust").unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        config.search.idf_weighting = true;
+
+        // "rust" is in every body but only one heading, so it is rare as a heading term
+        let heading = vec![SearchTerm::scoped("rust", Field::Heading)];
+        let results = search_files(&config, &heading).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].relevance, inverse_document_frequency(3, 1));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_keyword_pattern_phrase() {
+        let pattern = keyword_pattern("distributed  systems").unwrap();
+        assert!(pattern.is_match("Distributed systems"));
+        assert!(pattern.is_match("notes on distributed\n  systems design"));
+        assert!(!pattern.is_match("distributed file systems"));
+        assert!(keyword_pattern("a.b").unwrap().is_match("A.B") && !keyword_pattern("a.b").unwrap().is_match("axb"));
+        assert!(keyword_pattern(" ").is_err());
+    }
+
+    #[test]
+    fn test_file_extension() {
+        assert_eq!(file_extension("notes/a.MD").as_deref(), Some("md"));
+        assert_eq!(file_extension("notes/README"), None);
+        assert_eq!(file_label(None), "");
+    }
+
+    /// Counts the files read, to tell how much of the knowledge base a search scanned
+    #[derive(Default)]
+    struct CountingSource {
+        inner: FileSystemSource,
+        reads: AtomicUsize,
+    }
+
+    impl KnowledgeSource for CountingSource {
+        fn list_files(&self, config: &Config, warnings: &Warnings) -> Result<Vec<(PathBuf, f64)>> {
+            self.inner.list_files(config, warnings)
+        }
+
+        fn read_file(&self, path: &Path) -> Result<Option<Arc<Vec<u8>>>> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            self.inner.read_file(path)
+        }
+    }
+
+    #[test]
+    fn test_search_files_early_exit() {
+        let (temp_dir, mut config) = create_test_environment();
+        for i in 0..200 {
+            let path = temp_dir.path().join("notes").join(format!("extra{}.org", i));
+            fs::write(&path, "test test test").unwrap();
+        }
+        config.knowledge.max_files = 3;
+        config.search.early_exit = true;
+        config.search.min_score = 3.0;
+
+        // Scan with few threads, so that at most a handful of files are in flight when the limit is reached
+        let keywords = vec![SearchTerm::direct("test")];
+        let source = CountingSource::default();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let outcome = pool
+            .install(|| search_files_cancellable(&config, &keywords, 3, &AtomicBool::new(false), &source, &Warnings::default()))
+            .unwrap();
+
+        assert_eq!(outcome.results.len(), 3);
+        assert!(outcome.results.iter().all(|r| r.relevance >= 3.0));
+        // Every file was a candidate, but only a few were read
+        assert_eq!(outcome.candidates, 201);
+        let reads = source.reads.load(Ordering::Relaxed);
+        assert!(reads < 20, "read {} of 201 files", reads);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_early_exit_max_per_dir() {
+        let (temp_dir, mut config) = create_test_environment();
+        let crowded = temp_dir.path().join("notes").join("crowded");
+        fs::create_dir(&crowded).unwrap();
+        for i in 0..50 {
+            fs::write(crowded.join(format!("extra{}.org", i)), "test test test").unwrap();
+        }
+        for i in 0..3 {
+            let dir = temp_dir.path().join(format!("other{}", i));
+            fs::create_dir(&dir).unwrap();
+            fs::write(dir.join("note.org"), "test test test").unwrap();
+        }
+        config.search.early_exit = true;
+        config.search.min_score = 3.0;
+        config.search.max_per_dir = NonZeroUsize::new(1);
+
+        // Hits beyond the first in a directory are capped away, so they don't end the scan
+        let keywords = vec![SearchTerm::direct("test")];
+        let results = search_files_cancellable(
+            &config,
+            &keywords,
+            4,
+            &AtomicBool::new(false),
+            &FileSystemSource::default(),
+            &Warnings::default(),
+        )
+        .unwrap()
+        .results;
+        assert_eq!(results.len(), 4);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_ignore() {
+        let (temp_dir, mut config) = create_test_environment();
+        let archive_dir = temp_dir.path().join("archive");
+        fs::create_dir_all(&archive_dir).unwrap();
+        fs::write(archive_dir.join("old.org"), "test").unwrap();
+        fs::write(temp_dir.path().join("notes").join("draft.org"), "test").unwrap();
+
+        fs::write(temp_dir.path().join(".brainignore"), "archive/\n").unwrap();
+        config.knowledge.ignore = vec!["draft.org".to_string()];
+
+        let keywords = vec![SearchTerm::direct("test")];
+        let results = search_files(&config, &keywords).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("test.org"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_multiple_roots() {
+        let (temp_dir, mut config) = create_test_environment();
+        let other_root = tempdir().unwrap();
+        fs::write(other_root.path().join("best.org"), "test test test test test").unwrap();
+        fs::write(other_root.path().join("worst.org"), "test").unwrap();
+        config.knowledge.roots = vec![RootConfig {
+            path: other_root.path().to_string_lossy().to_string(),
+            weight: 1.0,
+        }];
+
+        let keywords = vec![SearchTerm::direct("test")];
+        let results = search_files(&config, &keywords).unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results.iter().map(|r| r.rank).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(results[0].path.ends_with("best.org"));
+        assert!(results[1].path.ends_with("test.org"));
+        assert!(results[2].path.ends_with("worst.org"));
+
+        drop(other_root);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_inaccessible_root() {
+        let (temp_dir, mut config) = create_test_environment();
+        let missing = temp_dir.path().join("unmounted");
+        config.knowledge.roots = vec![RootConfig {
+            path: missing.to_string_lossy().to_string(),
+            weight: 1.0,
+        }];
+
+        // The missing root is skipped and the other one still searched
+        let keywords = vec![SearchTerm::direct("test")];
+        let results = search_files(&config, &keywords).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("test.org"));
+
+        // Without any accessible root, searching fails
+        config.knowledge.root_path = missing.to_string_lossy().to_string();
+        let error = search_files(&config, &keywords).unwrap_err();
+        assert!(error.to_string().contains("does not exist"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_nan_score() {
+        let (temp_dir, mut config) = create_test_environment();
+        let other_root = tempdir().unwrap();
+        fs::write(other_root.path().join("a.org"), "test test test").unwrap();
+        fs::write(other_root.path().join("b.org"), "test").unwrap();
+        fs::write(other_root.path().join("c.org"), "unrelated").unwrap();
+        config.knowledge.roots = vec![RootConfig {
+            path: other_root.path().to_string_lossy().to_string(),
+            weight: f64::NAN,
+        }];
+
+        // Files scored NaN by the root weight are ranked last, in path order, with no relevance,
+        // while files matching nothing are left out
+        let keywords = vec![SearchTerm::direct("test")];
+        let results = search_files(&config, &keywords).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].path.ends_with("test.org"));
+        assert!(results[0].relevance > 0.0);
+        assert!(results[1].path.ends_with("a.org"));
+        assert!(results[2].path.ends_with("b.org"));
+        assert!(results[1..].iter().all(|r| r.relevance == 0.0));
+
+        // The same goes for a NaN weight applied to every file
+        config.knowledge.roots[0].weight = 1.0;
+        config.search.filename_weight = f64::NAN;
+        let results = search_files(&config, &keywords).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| !r.path.ends_with("c.org")));
+
+        drop(other_root);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_root_weight() {
+        let (temp_dir, mut config) = create_test_environment();
+        let other_root = tempdir().unwrap();
+        fs::write(other_root.path().join("noisy.org"), "test test test test test").unwrap();
+        config.knowledge.roots = vec![RootConfig {
+            path: other_root.path().to_string_lossy().to_string(),
+            weight: 0.1,
+        }];
+
+        let keywords = vec![SearchTerm::direct("test")];
+        let results = search_files(&config, &keywords).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].path.ends_with("test.org"));
+        assert!(results[1].path.ends_with("noisy.org"));
+        assert!((results[1].relevance - 0.5).abs() < 1e-9);
+
+        drop(other_root);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_fields() {
+        let (temp_dir, config) = create_test_environment();
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("tagged.org"), "* Memory  :rust:\nownership\n").unwrap();
+        fs::write(notes.join("heading.org"), "* Ownership\nabout rust\n").unwrap();
+
+        let tag = vec![SearchTerm::scoped("rust", Field::Tag)];
+        let results = search_files(&config, &tag).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("tagged.org"));
+
+        let heading = vec![SearchTerm::scoped("ownership", Field::Heading)];
+        let results = search_files(&config, &heading).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("heading.org"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_max_keywords_within() {
+        let mut offsets = vec![(0, 0), (500, 1), (520, 2), (530, 0), (900, 1)];
+        assert_eq!(max_keywords_within(&mut offsets, 50), 3);
+        assert_eq!(max_keywords_within(&mut offsets, 5), 1);
+        assert_eq!(max_keywords_within(&mut [], 50), 0);
+    }
+
+    #[test]
+    fn test_search_files_proximity() {
+        let (temp_dir, mut config) = create_test_environment();
+        config.search.proximity = true;
+        config.search.proximity_window = 20;
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("close.org"), "rust ownership").unwrap();
+        fs::write(notes.join("far.org"), format!("rust{}ownership", " ".repeat(100))).unwrap();
+
+        let keywords = vec![SearchTerm::direct("rust"), SearchTerm::direct("ownership")];
+        let results = search_files(&config, &keywords).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].path.ends_with("close.org"));
+        assert_eq!(results[0].relevance, 2.0 + config.search.proximity_boost);
+        assert_eq!(results[1].relevance, 2.0);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_ties_sorted_by_path() {
+        let (temp_dir, mut config) = create_test_environment();
+        config.knowledge.max_files = 3;
+        let notes = temp_dir.path().join("notes");
+        for name in ["e", "b", "d", "a", "c"] {
+            fs::write(notes.join(format!("{}.org", name)), "tie").unwrap();
+        }
+
+        let keywords = vec![SearchTerm::direct("tie")];
+        for _ in 0..10 {
+            let results = search_files(&config, &keywords).unwrap();
+            let names: Vec<&str> = results.iter().map(|r| &r.path[r.path.len() - 5..]).collect();
+            assert_eq!(names, vec!["a.org", "b.org", "c.org"]);
+        }
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_fold_diacritics() {
+        assert_eq!(fold_diacritics("Café crème, año"), "Cafe creme, ano");
+        assert_eq!(fold_diacritics("café"), "cafe");
+        assert_eq!(fold_diacritics("Ἀθῆναι, Йод"), "Αθηναι, Иод");
+        assert_eq!(fold_diacritics("ガード"), "ガード");
+        assert_eq!(fold_diacritics("バス パス"), "バス パス");
+    }
+
+    #[test]
+    fn test_search_files_fold_diacritics() {
+        let (temp_dir, mut config) = create_test_environment();
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("accents.org"), "Un café au lait").unwrap();
+
+        let keywords = vec![SearchTerm::direct("cafe")];
+        assert!(search_files(&config, &keywords).unwrap().is_empty());
+
+        config.search.fold_diacritics = true;
+        let results = search_files(&config, &keywords).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("accents.org"));
+
+        let keywords = vec![SearchTerm::direct("CAFÉ")];
+        assert_eq!(search_files(&config, &keywords).unwrap().len(), 1);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_windows_text() {
+        let (temp_dir, config) = create_test_environment();
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("windows.org"), "\u{feff}* Ownership  :rust:\r\nbody\r\n").unwrap();
+
+        let terms = vec![
+            SearchTerm::scoped("ownership", Field::Heading),
+            SearchTerm::scoped("rust", Field::Tag),
+        ];
+        let results = search_files(&config, &terms).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].relevance, 2.0);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_exclude_terms() {
+        let (temp_dir, mut config) = create_test_environment();
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("embedded.org"), "rust rust on Embedded devices").unwrap();
+        fs::write(notes.join("web.org"), "rust on the web").unwrap();
+        config.search.exclude_terms = vec!["embedded".to_string()];
+
+        let keywords = vec![SearchTerm::direct("rust")];
+        let results = search_files(&config, &keywords).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("web.org"));
+
+        config.search.exclude_weight = 0.1;
+        let results = search_files(&config, &keywords).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].path.ends_with("web.org"));
+        assert!((results[1].relevance - 0.2).abs() < 1e-9);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_limited() {
+        let (temp_dir, mut config) = create_test_environment();
+        config.knowledge.max_files = 1;
+        let notes = temp_dir.path().join("notes");
+        for name in ["a", "b", "c"] {
+            fs::write(notes.join(format!("{}.org", name)), "test").unwrap();
+        }
+
+        let keywords = vec![SearchTerm::direct("test")];
+        assert_eq!(search_files(&config, &keywords).unwrap().len(), 1);
+        assert_eq!(search_files_limited(&config, &keywords, 3).unwrap().len(), 3);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_sidecar() {
+        let (temp_dir, mut config) = create_test_environment();
+        config.knowledge.extensions = vec!["txt".to_string()];
+        config.knowledge.sidecar_suffix = Some(".meta".to_string());
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("list.txt"), "milk\neggs\n").unwrap();
+        fs::write(notes.join("list.txt.meta"), "title: Groceries\ntags: [errands]\n").unwrap();
+        fs::write(notes.join("other.txt"), "errands\n").unwrap();
+
+        let tag = vec![SearchTerm::scoped("errands", Field::Tag)];
+        let results = search_files(&config, &tag).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("list.txt"));
+
+        let heading = vec![SearchTerm::scoped("groceries", Field::Heading)];
+        assert_eq!(search_files(&config, &heading).unwrap().len(), 1);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_cancelled() {
+        let (temp_dir, config) = create_test_environment();
+
+        let keywords = vec![SearchTerm::direct("test")];
+        let source = FileSystemSource::default();
+        let result = search_files_cancellable(&config, &keywords, 5, &AtomicBool::new(true), &source, &Warnings::default());
+        assert!(result.is_err());
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_max_duration() {
+        let (temp_dir, mut config) = create_test_environment();
+        let keywords = vec![SearchTerm::direct("test")];
+        assert!(!search_files(&config, &keywords).unwrap().is_empty());
+
+        // An expired deadline ends the scan early with the results so far, rather than an error
+        config.search.max_duration_ms = Some(0);
+        assert!(search_files(&config, &keywords).unwrap().is_empty());
+
+        // The counting pass of IDF weighting stops at the deadline too
+        config.search.idf_weighting = true;
+        let source = CountingSource::default();
+        let outcome = search_files_cancellable(&config, &keywords, 5, &AtomicBool::new(false), &source, &Warnings::default());
+        assert!(outcome.unwrap().results.is_empty());
+        assert_eq!(source.reads.load(Ordering::Relaxed), 0);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_synonyms() {
+        let (temp_dir, mut config) = create_test_environment();
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("cluster.org"), "Notes on kubernetes deployments\n").unwrap();
+        let keywords = vec![SearchTerm::direct("K8s")];
+        assert!(search_files(&config, &keywords).unwrap().is_empty());
+
+        // The trigger matches regardless of case, and the synonym counts at its own weight
+        config.synonyms.insert("k8s".to_string(), vec!["kubernetes".to_string(), "K8S".to_string()]);
+        config.search.synonym_weight = 0.25;
+        let results = search_files(&config, &keywords).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("cluster.org"));
+        assert_eq!(results[0].relevance, 0.25);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_max_per_dir() {
+        let (temp_dir, mut config) = create_test_environment();
+        let notes = temp_dir.path().join("notes");
+        let meetings = notes.join("meetings");
+        fs::create_dir(&meetings).unwrap();
+        for i in 0..3 {
+            fs::write(meetings.join(format!("{}.org", i)), "budget budget budget").unwrap();
+        }
+        fs::write(notes.join("plan.org"), "budget").unwrap();
+        let keywords = vec![SearchTerm::direct("budget")];
+
+        config.knowledge.max_files = 2;
+        let results = search_files(&config, &keywords).unwrap();
+        assert!(results.iter().all(|r| r.path.contains("meetings")));
+
+        // Capping the meetings directory makes room for the weaker match elsewhere
+        config.search.max_per_dir = NonZeroUsize::new(1);
+        let results = search_files(&config, &keywords).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].path.contains("meetings"));
+        assert!(results[1].path.ends_with("plan.org"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_titles_only() {
+        let (temp_dir, mut config) = create_test_environment();
+        config.search.titles_only = true;
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("heading.org"), "* Ownership\nbody\n").unwrap();
+        fs::write(notes.join("body.org"), "* Rust\nownership ownership\n").unwrap();
+        fs::write(notes.join("ownership.org"), "* Rust\n").unwrap();
+
+        let keywords = vec![SearchTerm::direct("ownership")];
+        let results = search_files(&config, &keywords).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].path.ends_with("heading.org"));
+        assert!(results[1].path.ends_with("ownership.org"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_properties() {
+        let (temp_dir, mut config) = create_test_environment();
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("draft.org"), "* Notes\n:PROPERTIES:\n:STATUS: draft\n:END:\nrust\n").unwrap();
+        fs::write(notes.join("done.org"), "* Notes\n:PROPERTIES:\n:STATUS: done\n:END:\nrust\n").unwrap();
+
+        let terms = vec![
+            SearchTerm::direct("rust"),
+            SearchTerm::scoped("status=Draft", Field::Property),
+        ];
+        let results = search_files(&config, &terms).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].path.ends_with("draft.org"));
+
+        config.search.require_properties = true;
+        let results = search_files(&config, &terms).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("draft.org"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_markdown_fields() {
+        let (temp_dir, mut config) = create_test_environment();
+        config.knowledge.extensions = vec!["md".to_string()];
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("tagged.md"), "---\ntags: [rust]\n---\n# Memory\nownership\n").unwrap();
+        fs::write(notes.join("heading.md"), "# Ownership\nabout rust\n").unwrap();
+
+        let tag = vec![SearchTerm::scoped("rust", Field::Tag)];
+        let results = search_files(&config, &tag).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("tagged.md"));
+
+        let heading = vec![SearchTerm::scoped("ownership", Field::Heading)];
+        let results = search_files(&config, &heading).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("heading.md"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_filename() {
+        let (temp_dir, mut config) = create_test_environment();
+        fs::write(temp_dir.path().join("notes").join("rust-async.org"), "* Futures\n").unwrap();
+        let keywords = vec![SearchTerm::direct("rust"), SearchTerm::direct("async")];
+
+        // File names aren't searched by default
+        assert!(search_files(&config, &keywords).unwrap().is_empty());
+
+        config.search.filename_weight = 0.5;
+        let results = search_files(&config, &keywords).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("rust-async.org"));
+        assert_eq!(results[0].relevance, 2.0 * config.search.filename_weight);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_location_weights() {
+        let (temp_dir, mut config) = create_test_environment();
+        config.knowledge.extensions = vec!["md".to_string()];
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("tokio.md"), "# Tokio runtime\n\nThe tokio scheduler\n").unwrap();
+        let keywords = vec![SearchTerm::direct("tokio")];
+        let relevance = |config: &Config| search_files(config, &keywords).unwrap()[0].relevance;
+
+        // By default, only the two body matches count, once each
+        assert_eq!(relevance(&config), 2.0);
+
+        // Each location is weighted separately: 2 body, 1 heading and 1 file name match
+        config.search.body_weight = 2.0;
+        config.search.heading_weight = 3.0;
+        config.search.filename_weight = 0.0;
+        assert_eq!(relevance(&config), 2.0 * 2.0 + 1.0 * 3.0);
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_sort() {
+        let (temp_dir, mut config) = create_test_environment();
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("b.org"), "test").unwrap();
+        fs::write(notes.join("a.org"), "test").unwrap();
+        let newest = File::create(notes.join("c.org")).unwrap();
+        write!(&newest, "test").unwrap();
+        newest.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60)).unwrap();
+        config.knowledge.max_files = 2;
+
+        let keywords = vec![SearchTerm::direct("test")];
+
+        config.search.sort = SortOrder::Path;
+        let results = search_files(&config, &keywords).unwrap();
+        assert!(results[0].path.ends_with("a.org"));
+        assert!(results[1].path.ends_with("b.org"));
+
+        config.search.sort = SortOrder::Mtime;
+        let results = search_files(&config, &keywords).unwrap();
+        assert!(results[0].path.ends_with("c.org"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_search_files_include_exclude() {
+        let (temp_dir, mut config) = create_test_environment();
+        let notes = temp_dir.path().join("notes");
+        fs::write(notes.join("readme.md"), "test").unwrap();
+        fs::write(notes.join("old.org_archive"), "test").unwrap();
+        fs::write(notes.join("2024-01-01-journal.org"), "test").unwrap();
+
+        let keywords = vec![SearchTerm::direct("test")];
+
+        config.knowledge.extensions = vec!["org".to_string(), "md".to_string()];
+        let results = search_files(&config, &keywords).unwrap();
+        assert_eq!(results.len(), 3);
+
+        config.knowledge.include = vec!["**/*.org".to_string(), "**/*.org_archive".to_string()];
+        config.knowledge.exclude = vec!["**/*-journal.org".to_string()];
+        let mut paths: Vec<String> = search_files(&config, &keywords)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].ends_with("old.org_archive"));
+        assert!(paths[1].ends_with("test.org"));
+
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_match_score() {
+        assert_eq!(match_score(0, false), 0.0);
+        assert_eq!(match_score(100, false), 100.0);
+
+        assert_eq!(match_score(0, true), 0.0);
+        assert!((match_score(1, true) - std::f64::consts::LN_2).abs() < 1e-12);
+        assert!((match_score(100, true) - 101f64.ln()).abs() < 1e-12);
+        // 50 times the matches are worth less than 5 times the score
+        assert!(match_score(100, true) < 5.0 * match_score(2, true));
+    }
+
+    #[test]
+    fn test_flatten_text() {
+        let terms = vec![
+            SearchTerm::direct("rust"),
+            SearchTerm::scoped("Overview", Field::Heading),
+            SearchTerm::scoped("work", Field::Tag),
+            SearchTerm::scoped("STATUS=done", Field::Property),
+        ];
+
+        assert_eq!(SearchTerm::flatten_text(&terms), vec!["rust", "Overview"]);
+    }
+
+    #[test]
+    fn test_search_files_recalled_weight() {
+        let (temp_dir, config) = create_test_environment();
+
+        let direct = vec![SearchTerm::direct("keywords")];
+        let recalled = vec![SearchTerm {
+            origin: TermOrigin::Recalled,
+            ..SearchTerm::direct("keywords")
+        }];
+        let direct_results = search_files(&config, &direct).unwrap();
+        let recalled_results = search_files(&config, &recalled).unwrap();
+
+        assert_eq!(direct_results[0].relevance, 1.0);
+        assert_eq!(recalled_results[0].relevance, config.search.recalled_weight);
+
+        drop(temp_dir);
+    }
 }
@@ -0,0 +1,386 @@
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::runtime::Handle;
+use url::Url;
+use walkdir::WalkDir;
+
+use crate::cache::FileCache;
+use crate::config::{Config, SourceKind};
+use crate::Warnings;
+
+/// Where the files of the knowledge base are listed and read from
+pub trait KnowledgeSource: Send + Sync {
+    /// Lists the files to search, each with the weight of the root it belongs to
+    /// Problems that don't prevent listing, like a skipped root, are reported to `warnings`.
+    fn list_files(&self, config: &Config, warnings: &Warnings) -> Result<Vec<(PathBuf, f64)>>;
+
+    /// Reads a file's bytes, returning None if it doesn't exist
+    fn read_file(&self, path: &Path) -> Result<Option<Arc<Vec<u8>>>>;
+
+    /// Reads up to `max_bytes` of a file, returning None if it doesn't exist
+    /// The flag is true if the whole file was read. Sources that can't read part of a file return all of it.
+    fn read_file_prefix(&self, path: &Path, _max_bytes: usize) -> Result<Option<(Arc<Vec<u8>>, bool)>> {
+        Ok(self.read_file(path)?.map(|bytes| (bytes, true)))
+    }
+
+    /// Returns when a file was last modified, if the source knows
+    fn modified(&self, _path: &Path) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// Creates the knowledge source selected by `knowledge.source`
+pub fn source_for(config: &Config) -> Result<Arc<dyn KnowledgeSource>> {
+    let cache = FileCache::new(config.content.cache_max_bytes);
+    Ok(match config.knowledge.source {
+        SourceKind::Filesystem => Arc::new(FileSystemSource::new(cache)),
+        SourceKind::Http => {
+            let base_url = config
+                .knowledge
+                .base_url
+                .as_deref()
+                .context("knowledge.base_url is required when knowledge.source is \"http\"")?;
+            Arc::new(HttpSource::new(base_url, cache)?)
+        }
+    })
+}
+
+/// Files under the configured knowledge base roots on the local filesystem
+#[derive(Debug, Default)]
+pub struct FileSystemSource {
+    cache: FileCache,
+}
+
+impl FileSystemSource {
+    /// Creates a source reading files through the given cache
+    pub fn new(cache: FileCache) -> Self {
+        Self { cache }
+    }
+}
+
+impl KnowledgeSource for FileSystemSource {
+    fn list_files(&self, config: &Config, warnings: &Warnings) -> Result<Vec<(PathBuf, f64)>> {
+        let roots = config.knowledge.roots();
+        if roots.is_empty() {
+            return Err(anyhow::anyhow!("No knowledge base path configured"));
+        }
+
+        // Roots that can't be read, e.g. on a network mount that is down, are skipped
+        // as long as another root can be searched
+        let mut files = Vec::new();
+        let mut inaccessible = Vec::new();
+        for &(root, root_weight) in &roots {
+            if let Err(e) = check_root(Path::new(root)) {
+                inaccessible.push(e);
+                continue;
+            }
+            files.extend(
+                collect_files(Path::new(root), config)?
+                    .into_iter()
+                    .map(|path| (path, root_weight)),
+            );
+        }
+        if inaccessible.len() == roots.len() {
+            let errors: Vec<String> = inaccessible.iter().map(|e| format!("{:#}", e)).collect();
+            return Err(anyhow::anyhow!("{}", errors.join("; ")));
+        }
+        for e in inaccessible {
+            warnings.warn(format_args!("Skipping knowledge base root: {:#}", e));
+        }
+        Ok(files)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Option<Arc<Vec<u8>>>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(self.cache.read(path)?))
+    }
+
+    fn read_file_prefix(&self, path: &Path, max_bytes: usize) -> Result<Option<(Arc<Vec<u8>>, bool)>> {
+        let Ok(metadata) = fs::metadata(path) else {
+            return Ok(None);
+        };
+        if metadata.len() <= max_bytes as u64 {
+            return Ok(self.read_file(path)?.map(|bytes| (bytes, true)));
+        }
+
+        // Stop reading at the limit rather than loading the whole file, which isn't cached
+        let mut bytes = Vec::with_capacity(max_bytes);
+        fs::File::open(path)?.take(max_bytes as u64).read_to_end(&mut bytes)?;
+        Ok(Some((Arc::new(bytes), false)))
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+}
+
+/// Files served over HTTP
+/// `GET {base_url}/files` returns a JSON array of relative paths, and
+/// `GET {base_url}/files/{path}` returns the content of a file.
+pub struct HttpSource {
+    base_url: Url,
+    client: reqwest::Client,
+    runtime: Handle,
+    cache: FileCache,
+}
+
+impl HttpSource {
+    /// Creates a source for the server at `base_url`
+    /// Requests run on the current Tokio runtime, so this must be called within one
+    pub fn new(base_url: &str, cache: FileCache) -> Result<Self> {
+        let base_url = Url::parse(base_url).with_context(|| format!("Invalid knowledge base URL: {}", base_url))?;
+        if base_url.cannot_be_a_base() {
+            return Err(anyhow::anyhow!("Invalid knowledge base URL: {}", base_url));
+        }
+        let runtime = Handle::try_current().context("The HTTP knowledge source needs a Tokio runtime")?;
+
+        Ok(Self {
+            base_url,
+            client: reqwest::Client::new(),
+            runtime,
+            cache,
+        })
+    }
+
+    /// Builds the URL under `files/` for a relative path, escaping each component
+    fn files_url(&self, path: &Path) -> Url {
+        let mut url = self.base_url.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push("files");
+            segments.extend(path.iter().map(|component| component.to_string_lossy()));
+        }
+        url
+    }
+}
+
+impl KnowledgeSource for HttpSource {
+    fn list_files(&self, config: &Config, _warnings: &Warnings) -> Result<Vec<(PathBuf, f64)>> {
+        let url = self.files_url(Path::new(""));
+        let paths: Vec<String> = self.runtime.block_on(async {
+            self.client.get(url.clone()).send().await?.error_for_status()?.json().await
+        })
+        .with_context(|| format!("Failed to list files from {}", url))?;
+
+        let ignore = build_ignore(Path::new(""), None, config)?;
+        let include = build_globs(&config.knowledge.include)?;
+        let exclude = build_globs(&config.knowledge.exclude)?;
+        Ok(paths
+            .iter()
+            .map(|path| PathBuf::from(path.trim_start_matches('/')))
+            .filter(|path| !ignore.matched_path_or_any_parents(path, false).is_ignore())
+            .filter(|path| is_included(path, path, config, &include, &exclude))
+            .map(|path| (path, 1.0))
+            .collect())
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Option<Arc<Vec<u8>>>> {
+        let url = self.files_url(path);
+        // Fail with None for a missing file, so that it isn't cached but isn't an error either
+        let read = || -> std::result::Result<Vec<u8>, Option<anyhow::Error>> {
+            self.runtime.block_on(async {
+                let response = self.client.get(url.clone()).send().await.map_err(|e| Some(e.into()))?;
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Err(None);
+                }
+                let response = response.error_for_status().map_err(|e| Some(e.into()))?;
+                Ok(response.bytes().await.map_err(|e| Some(e.into()))?.to_vec())
+            })
+        };
+        // Without modification times, each file is fetched at most once per run
+        match self.cache.get_or_read(path, SystemTime::UNIX_EPOCH, read) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(None) => Ok(None),
+            Err(Some(e)) => Err(e.context(format!("Failed to fetch {}", url))),
+        }
+    }
+}
+
+/// Returns whether a path is a file searched under one of the configured roots, or its sidecar
+/// Roots are compared in canonical form, so `path` should be absolute and canonical.
+pub fn is_knowledge_file(path: &Path, config: &Config) -> Result<bool> {
+    // A change to a sidecar file counts as a change to its note
+    let note = config
+        .knowledge
+        .sidecar_suffix
+        .as_deref()
+        .and_then(|suffix| path.to_str()?.strip_suffix(suffix))
+        .map(PathBuf::from);
+    let path = note.as_deref().unwrap_or(path);
+
+    for (root, _) in config.knowledge.roots() {
+        let Ok(root_path) = fs::canonicalize(root) else { continue };
+        let Ok(relative) = path.strip_prefix(&root_path) else { continue };
+
+        let ignore = build_ignore(&root_path, Some(&root_path.join(".brainignore")), config)?;
+        if ignore.matched_path_or_any_parents(path, false).is_ignore() {
+            continue;
+        }
+        let include = build_globs(&config.knowledge.include)?;
+        let exclude = build_globs(&config.knowledge.exclude)?;
+        if is_included(path, relative, config, &include, &exclude) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Builds the matcher for files to skip from a `.brainignore` file, if any, and the configured ignore patterns
+fn build_ignore(root_path: &Path, brainignore: Option<&Path>, config: &Config) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root_path);
+
+    if let Some(brainignore) = brainignore.filter(|path| path.exists()) {
+        if let Some(e) = builder.add(brainignore) {
+            return Err(e.into());
+        }
+    }
+    for pattern in &config.knowledge.ignore {
+        builder.add_line(None, pattern)?;
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Builds a glob set from a list of patterns
+fn build_globs(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Returns whether a file matches the include globs (or, without any, has one of the
+/// configured extensions) and doesn't match the exclude globs
+fn is_included(path: &Path, relative: &Path, config: &Config, include: &GlobSet, exclude: &GlobSet) -> bool {
+    let included = if config.knowledge.include.is_empty() {
+        path.extension().is_some_and(|ext| {
+            config.knowledge.extensions.iter().any(|allowed| ext == allowed.as_str())
+        })
+    } else {
+        include.is_match(relative)
+    };
+    included && !exclude.is_match(relative)
+}
+
+/// Fails if a knowledge base root doesn't exist or can't be listed
+fn check_root(root_path: &Path) -> Result<()> {
+    if !root_path.exists() {
+        return Err(anyhow::anyhow!("Knowledge base path does not exist: {}", root_path.display()));
+    }
+    fs::read_dir(root_path)
+        .with_context(|| format!("Knowledge base path is not readable: {}", root_path.display()))?;
+    Ok(())
+}
+
+/// Collects all files under a knowledge base root that should be searched
+/// A file is searched if it's included by `is_included` and isn't ignored
+fn collect_files(root_path: &Path, config: &Config) -> Result<Vec<PathBuf>> {
+    let ignore = build_ignore(root_path, Some(&root_path.join(".brainignore")), config)?;
+    let include = build_globs(&config.knowledge.include)?;
+    let exclude = build_globs(&config.knowledge.exclude)?;
+
+    let files = WalkDir::new(root_path)
+        .into_iter()
+        .filter_entry(|e| !ignore.matched(e.path(), e.file_type().is_dir()).is_ignore())
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            let relative = e.path().strip_prefix(root_path).unwrap_or(e.path());
+            is_included(e.path(), relative, config, &include, &exclude)
+        })
+        .map(|e| e.path().to_path_buf())
+        .collect();
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::create_test_config_for_tests;
+    use crate::content::get_section;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serves a file list and one file, answering 404 for anything else
+    fn serve_files() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                BufReader::new(&stream).read_line(&mut request_line).unwrap();
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body) = match path {
+                    "/kb/files" => ("200 OK", r#"["notes/a.org", "notes/b.md", "drafts/c.org"]"#),
+                    "/kb/files/notes/a.org" => ("200 OK", "* Remote note"),
+                    _ => ("404 Not Found", ""),
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{}/kb/", address)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_http_source() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        config.knowledge.ignore = vec!["drafts/".to_string()];
+        let source = HttpSource::new(&serve_files(), FileCache::new(1024)).unwrap();
+
+        let (files, found, missing, section) = tokio::task::spawn_blocking(move || {
+            let files = source.list_files(&config, &Warnings::default()).unwrap();
+            let found = source.read_file(Path::new("notes/a.org")).unwrap();
+            let missing = source.read_file(Path::new("notes/missing.org")).unwrap();
+            let section = get_section("notes/a.org", "remote note", true, &source).unwrap();
+            (files, found, missing, section)
+        })
+        .await
+        .unwrap();
+
+        // Only .org files are searched by default, and drafts/ is ignored
+        assert_eq!(files, vec![(PathBuf::from("notes/a.org"), 1.0)]);
+        assert_eq!(found.unwrap().as_slice(), b"* Remote note");
+        assert!(missing.is_none());
+        assert_eq!(section, "* Remote note");
+    }
+
+    #[test]
+    fn test_is_knowledge_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+        fs::write(root.join(".brainignore"), "drafts/\n").unwrap();
+        let mut config = create_test_config_for_tests(&root);
+        config.knowledge.sidecar_suffix = Some(".meta".to_string());
+
+        assert!(is_knowledge_file(&root.join("notes/a.org"), &config).unwrap());
+        assert!(is_knowledge_file(&root.join("notes/a.org.meta"), &config).unwrap());
+        assert!(!is_knowledge_file(&root.join("notes/a.txt"), &config).unwrap());
+        assert!(!is_knowledge_file(&root.join("drafts/b.org"), &config).unwrap());
+        assert!(!is_knowledge_file(Path::new("/elsewhere/c.org"), &config).unwrap());
+    }
+
+    #[test]
+    fn test_source_for_http_requires_base_url() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = create_test_config_for_tests(temp_dir.path());
+        config.knowledge.source = SourceKind::Http;
+        assert!(source_for(&config).is_err());
+    }
+}
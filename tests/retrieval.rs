@@ -0,0 +1,60 @@
+//! Ranking regression tests against the fixture corpus in `tests/fixtures/corpus`
+
+use brain::config::Config;
+use brain::search::SearchResult;
+use brain::Brain;
+use std::path::Path;
+
+/// Builds a configuration searching the fixture corpus
+fn fixture_config() -> Config {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/corpus");
+    let config = format!(
+        r#"
+        [knowledge]
+        root_path = "{}"
+        max_files = 5
+
+        [ollama]
+        endpoint = "http://localhost:11434"
+        model = "unused"
+        max_context_length = 4096
+        "#,
+        corpus.display()
+    );
+    toml::from_str(&config).unwrap()
+}
+
+/// Returns the file names of the results, in order
+fn ranked_names(results: &[SearchResult]) -> Vec<String> {
+    results
+        .iter()
+        .map(|r| Path::new(&r.path).file_name().unwrap().to_string_lossy().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_retrieve_ranking() {
+    let brain = Brain::new(fixture_config()).unwrap();
+
+    let retrieval = brain.retrieve(&Brain::split_terms("rust ownership"), false).await.unwrap();
+    assert_eq!(ranked_names(&retrieval.results), vec!["rust.org", "python.org"]);
+    assert!(retrieval.contents.is_none());
+
+    let retrieval = brain.retrieve(&Brain::split_terms("sourdough"), false).await.unwrap();
+    assert_eq!(ranked_names(&retrieval.results), vec!["bread.org"]);
+
+    // Only org files are searched by default
+    let retrieval = brain.retrieve(&Brain::split_terms("reading"), false).await.unwrap();
+    assert!(retrieval.results.is_empty());
+}
+
+#[tokio::test]
+async fn test_retrieve_contents() {
+    let brain = Brain::new(fixture_config()).unwrap();
+
+    let retrieval = brain.retrieve(&Brain::split_terms("borrow"), true).await.unwrap();
+    assert_eq!(ranked_names(&retrieval.results), vec!["rust.org"]);
+    let contents = retrieval.contents.unwrap();
+    assert!(contents.contains("borrow checker"));
+    assert!(!contents.contains("Sourdough"));
+}